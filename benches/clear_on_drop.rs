@@ -1,5 +1,6 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 
+use clear_on_drop::wipe::{clear_slice, Stores};
 use clear_on_drop::ClearOnDrop;
 
 fn clear_on_drop_small(c: &mut Criterion) {
@@ -44,10 +45,42 @@ fn clear_on_drop_large(c: &mut Criterion) {
     });
 }
 
+fn clear_slice_large(c: &mut Criterion) {
+    let mut place = [[0u64; 32]; 32];
+    c.bench_function("clear_slice_large", |b| {
+        b.iter(|| clear_slice(&mut place, Stores::Regular))
+    });
+}
+
+fn clear_slice_large_non_temporal(c: &mut Criterion) {
+    let mut place = [[0u64; 32]; 32];
+    c.bench_function("clear_slice_large_non_temporal", |b| {
+        b.iter(|| clear_slice(&mut place, Stores::NonTemporal))
+    });
+}
+
+fn clear_slice_huge(c: &mut Criterion) {
+    let mut place = vec![0u64; 1 << 20];
+    c.bench_function("clear_slice_huge", |b| {
+        b.iter(|| clear_slice(&mut place, Stores::Regular))
+    });
+}
+
+fn clear_slice_huge_non_temporal(c: &mut Criterion) {
+    let mut place = vec![0u64; 1 << 20];
+    c.bench_function("clear_slice_huge_non_temporal", |b| {
+        b.iter(|| clear_slice(&mut place, Stores::NonTemporal))
+    });
+}
+
 criterion_group!(
     benches,
     clear_on_drop_small,
     clear_on_drop_medium,
-    clear_on_drop_large
+    clear_on_drop_large,
+    clear_slice_large,
+    clear_slice_large_non_temporal,
+    clear_slice_huge,
+    clear_slice_huge_non_temporal
 );
 criterion_main!(benches);
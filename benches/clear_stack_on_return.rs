@@ -1,6 +1,6 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 
-use clear_on_drop::{clear_stack_on_return, clear_stack_on_return_fnonce};
+use clear_on_drop::{clear_stack_bytes, clear_stack_on_return, clear_stack_on_return_fnonce};
 
 fn clear_stack_on_return_tiny(c: &mut Criterion) {
     c.bench_function("clear_stack_on_return_tiny", |b| {
@@ -26,11 +26,32 @@ fn clear_stack_on_return_fnonce_small(c: &mut Criterion) {
     });
 }
 
+fn clear_stack_bytes_tiny(c: &mut Criterion) {
+    c.bench_function("clear_stack_bytes_tiny", |b| {
+        b.iter(|| clear_stack_bytes(256))
+    });
+}
+
+fn clear_stack_bytes_small(c: &mut Criterion) {
+    c.bench_function("clear_stack_bytes_small", |b| {
+        b.iter(|| clear_stack_bytes(2 * 4096))
+    });
+}
+
+fn clear_stack_bytes_large(c: &mut Criterion) {
+    c.bench_function("clear_stack_bytes_large", |b| {
+        b.iter(|| clear_stack_bytes(64 * 4096))
+    });
+}
+
 criterion_group!(
     benches,
     clear_stack_on_return_tiny,
     clear_stack_on_return_small,
     clear_stack_on_return_fnonce_tiny,
-    clear_stack_on_return_fnonce_small
+    clear_stack_on_return_fnonce_small,
+    clear_stack_bytes_tiny,
+    clear_stack_bytes_small,
+    clear_stack_bytes_large
 );
 criterion_main!(benches);
@@ -1,7 +1,67 @@
+use std::env;
+use std::process::Command;
+use std::str;
+
+/// The architectures where inline assembly is stable.
+const ASM_ARCHS: &[&str] = &[
+    "x86",
+    "x86_64",
+    "arm",
+    "aarch64",
+    "riscv32",
+    "riscv64",
+    "loongarch64",
+];
+
 fn main() {
-    if !cfg!(feature = "no_cc") {
-        cc::Build::new()
-            .file("src/hide.c")
-            .compile("clear_on_drop");
+    println!("cargo:rustc-check-cfg=cfg(clear_on_drop_avx512f)");
+    // `#[target_feature(enable = "avx512f")]` is stable since Rust 1.89.
+    if rustc_minor_version().is_some_and(|minor| minor >= 89) {
+        println!("cargo:rustc-cfg=clear_on_drop_avx512f");
     }
+
+    // The implementation of the hiding (see src/hide.rs), which the
+    // verify test also needs to know.
+    let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+    let hide = if cfg!(feature = "nightly") {
+        "nightly"
+    } else if cfg!(feature = "cc") && !cfg!(feature = "no_cc") {
+        "cc"
+    } else if !cfg!(feature = "no_asm") && ASM_ARCHS.contains(&&*arch) {
+        "asm"
+    } else {
+        "fallback"
+    };
+    println!(
+        "cargo:rustc-check-cfg=cfg(clear_on_drop_hide, values(\"nightly\", \"cc\", \"asm\", \"fallback\"))"
+    );
+    println!("cargo:rustc-cfg=clear_on_drop_hide=\"{}\"", hide);
+
+    // The verify profile is the only one with both optimization and debug
+    // information; build scripts can't see that it also enables link-time
+    // optimization, which the verify test needs to know.
+    println!("cargo:rustc-check-cfg=cfg(clear_on_drop_lto)");
+    let optimized = env::var("OPT_LEVEL").is_ok_and(|level| level != "0");
+    let debug = env::var("DEBUG").is_ok_and(|debug| debug != "false");
+    if optimized && debug {
+        println!("cargo:rustc-cfg=clear_on_drop_lto");
+    }
+
+    #[cfg(feature = "cc")]
+    {
+        if hide == "cc" {
+            cc::Build::new().file("src/hide.c").compile("clear_on_drop");
+        }
+    }
+}
+
+fn rustc_minor_version() -> Option<u32> {
+    let rustc = env::var_os("RUSTC")?;
+    let output = Command::new(rustc).arg("--version").output().ok()?;
+    let version = str::from_utf8(&output.stdout).ok()?;
+    let mut pieces = version.split('.');
+    if pieces.next() != Some("rustc 1") {
+        return None;
+    }
+    pieces.next()?.parse().ok()
 }
@@ -0,0 +1,192 @@
+#![deny(missing_docs)]
+
+//! Derive macros for the `clear_on_drop` crate.
+//!
+//! This crate provides `#[derive(ZeroSafe)]` and
+//! `#[derive(InitializableFromZeroed)]`, which allow a structure to be
+//! used with `clear_on_drop::clear::Clear` without implementing
+//! `Default` for it. It is usually used through the `derive` feature of
+//! the `clear_on_drop` crate, instead of directly.
+
+extern crate proc_macro;
+
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Error, Field};
+
+/// Derives `clear_on_drop::clear::ZeroSafe` for a structure or union.
+///
+/// Every field must itself be `ZeroSafe`; this is checked at compile
+/// time. Enums cannot derive `ZeroSafe`, since a discriminant of zero
+/// is not necessarily valid.
+///
+/// # Example
+///
+/// ```
+/// # use clear_on_drop_derive::ZeroSafe;
+/// #[derive(ZeroSafe)]
+/// struct RoundKeys {
+///     keys: [[u32; 4]; 11],
+///     rounds: usize,
+/// }
+///
+/// fn check<T: clear_on_drop::clear::ZeroSafe>() {}
+/// check::<RoundKeys>();
+/// ```
+///
+/// ```compile_fail
+/// # use clear_on_drop_derive::ZeroSafe;
+/// #[derive(ZeroSafe)]
+/// struct NotZeroSafe {
+///     name: &'static str,
+/// }
+/// ```
+#[proc_macro_derive(ZeroSafe)]
+pub fn derive_zero_safe(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_zero_safe(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Derives `clear_on_drop::clear::InitializableFromZeroed` for a
+/// structure.
+///
+/// Each field is initialized through its own `InitializableFromZeroed`
+/// implementation, which for most types means its `Default` value.
+/// Fields marked with `#[zero_safe]` are left as all-bits-zero; their
+/// types must be `ZeroSafe`, which is checked at compile time.
+///
+/// Since `InitializableFromZeroed` is implemented for every `Default`
+/// type, this cannot be derived for a type which also implements
+/// `Default`.
+///
+/// # Example
+///
+/// ```
+/// # use clear_on_drop::clear::Clear;
+/// # use clear_on_drop_derive::InitializableFromZeroed;
+/// #[derive(InitializableFromZeroed)]
+/// struct KeySchedule {
+///     #[zero_safe]
+///     keys: [[u32; 4]; 15],
+///     name: Option<String>,
+/// }
+///
+/// let mut place = KeySchedule {
+///     keys: [[0x41414141; 4]; 15],
+///     name: Some("aes256".to_owned()),
+/// };
+/// place.clear();
+/// assert_eq!(place.keys, [[0; 4]; 15]);
+/// assert_eq!(place.name, None);
+/// ```
+#[proc_macro_derive(InitializableFromZeroed, attributes(zero_safe))]
+pub fn derive_initializable_from_zeroed(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_initializable_from_zeroed(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand_zero_safe(mut input: DeriveInput) -> syn::Result<TokenStream> {
+    let fields: Vec<&Field> = match &input.data {
+        Data::Struct(data) => data.fields.iter().collect(),
+        Data::Union(data) => data.fields.named.iter().collect(),
+        Data::Enum(data) => {
+            return Err(Error::new(
+                data.enum_token.span(),
+                "ZeroSafe cannot be derived for enums",
+            ))
+        }
+    };
+
+    let bounds: Vec<_> = fields
+        .iter()
+        .map(|field| {
+            let ty = &field.ty;
+            quote_spanned!(ty.span()=> #ty: ::clear_on_drop::clear::ZeroSafe)
+        })
+        .collect();
+    let where_clause = input.generics.make_where_clause();
+    for bound in bounds {
+        where_clause.predicates.push(parse_quote!(#bound));
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        unsafe impl #impl_generics ::clear_on_drop::clear::ZeroSafe
+            for #name #ty_generics #where_clause {}
+    })
+}
+
+fn expand_initializable_from_zeroed(mut input: DeriveInput) -> syn::Result<TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        Data::Enum(data) => {
+            return Err(Error::new(
+                data.enum_token.span(),
+                "InitializableFromZeroed cannot be derived for enums",
+            ))
+        }
+        Data::Union(data) => {
+            return Err(Error::new(
+                data.union_token.span(),
+                "InitializableFromZeroed cannot be derived for unions",
+            ))
+        }
+    };
+
+    let mut bounds = Vec::new();
+    let mut initializers = Vec::new();
+    for (member, field) in fields.members().zip(fields.iter()) {
+        let ty = &field.ty;
+        if is_zero_safe(field)? {
+            bounds.push(quote_spanned!(ty.span()=> #ty: ::clear_on_drop::clear::ZeroSafe));
+        } else {
+            bounds.push(quote_spanned! {ty.span()=>
+                #ty: ::clear_on_drop::clear::InitializableFromZeroed
+            });
+            initializers.push(quote_spanned! {ty.span()=>
+                ::clear_on_drop::clear::InitializableFromZeroed::initialize(
+                    ::core::ptr::addr_of_mut!((*place).#member),
+                );
+            });
+        }
+    }
+    let where_clause = input.generics.make_where_clause();
+    for bound in bounds {
+        where_clause.predicates.push(parse_quote!(#bound));
+    }
+
+    let place = if initializers.is_empty() {
+        quote!(_place)
+    } else {
+        quote!(place)
+    };
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::clear_on_drop::clear::InitializableFromZeroed
+            for #name #ty_generics #where_clause
+        {
+            #[inline]
+            unsafe fn initialize(#place: *mut Self) {
+                #(#initializers)*
+            }
+        }
+    })
+}
+
+fn is_zero_safe(field: &Field) -> syn::Result<bool> {
+    let mut zero_safe = false;
+    for attr in &field.attrs {
+        if attr.path().is_ident("zero_safe") {
+            attr.meta.require_path_only()?;
+            zero_safe = true;
+        }
+    }
+    Ok(zero_safe)
+}
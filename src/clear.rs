@@ -38,11 +38,49 @@
 //! }
 //! assert!(!as_bytes(&place).contains(&0x41));
 //! ```
+//!
+//! # Deriving
+//!
+//! Types which should not implement `Default` can instead derive
+//! `InitializableFromZeroed` and `ZeroSafe`, through the `derive`
+//! feature. Fields marked with `#[zero_safe]` are left as all-bits-zero,
+//! and the others are initialized individually.
+//!
+//! ```
+//! # #[cfg(feature = "derive")]
+//! # {
+//! # use clear_on_drop::clear::{Clear, InitializableFromZeroed};
+//! #[derive(InitializableFromZeroed)]
+//! struct KeySchedule {
+//!     #[zero_safe]
+//!     round_keys: [[u32; 4]; 15],
+//!     rounds: usize,
+//! }
+//!
+//! let mut place = KeySchedule { round_keys: [[0x41414141; 4]; 15], rounds: 14 };
+//! place.clear();
+//! assert_eq!(place.round_keys, [[0; 4]; 15]);
+//! assert_eq!(place.rounds, 0);
+//! # }
+//! ```
 
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+use core::cell::{Cell, UnsafeCell};
+use core::fmt;
 use core::mem;
-use core::ptr;
+use core::num::{
+    NonZeroI128, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize, NonZeroU128,
+    NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8, NonZeroUsize, Wrapping,
+};
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
 
 use crate::hide::hide_mem_impl;
+use crate::wipe::{flush_bytes, WipePattern};
+
+#[cfg(feature = "derive")]
+pub use clear_on_drop_derive::{InitializableFromZeroed, ZeroSafe};
 
 /// An operation to completely overwrite a value, without leaking data.
 ///
@@ -52,6 +90,33 @@ use crate::hide::hide_mem_impl;
 pub trait Clear {
     /// Completely overwrites this value.
     fn clear(&mut self);
+
+    /// Completely overwrites this value, and flushes the overwritten
+    /// memory from the cache hierarchy.
+    ///
+    /// This makes the zeros reach main memory as soon as possible, at
+    /// the cost of a slower clear and of later cache misses. See
+    /// `wipe::flush` for details.
+    #[inline]
+    fn clear_and_flush(&mut self) {
+        self.clear();
+        unsafe { flush_bytes(self as *mut Self as *const u8, mem::size_of_val(self)) }
+    }
+
+    /// Completely overwrites this value, overwriting it with `pattern`
+    /// before setting it to all-bits-zero.
+    ///
+    /// This is slower than `Clear::clear`, and should be used only when
+    /// required by some external policy.
+    ///
+    /// The default implementation only calls `Clear::clear`, since a
+    /// pattern can't be written over a value of an unknown type; the
+    /// implementation for `InitializableFromZeroed` types writes it.
+    #[inline]
+    fn clear_with(&mut self, pattern: &WipePattern) {
+        let _ = pattern;
+        self.clear();
+    }
 }
 
 impl<T: ?Sized> Clear for T
@@ -69,6 +134,39 @@ where
             Self::initialize(ptr);
         }
     }
+
+    #[inline]
+    fn clear_with(&mut self, pattern: &WipePattern) {
+        let size = mem::size_of_val(self);
+        unsafe {
+            let ptr = self as *mut Self;
+            ptr::drop_in_place(ptr);
+            // The pattern might panic, after the value was dropped; the
+            // guard still zeroes and initializes the place on unwind.
+            let _guard = Reinitialize { ptr, size };
+            // Sets the padding, so the pattern only sees initialized bytes.
+            ptr::write_bytes(ptr as *mut u8, 0, size);
+            pattern.overwrite(ptr as *mut u8, size);
+        }
+    }
+}
+
+/// Sets a place to zero and initializes it when dropped, even while
+/// unwinding.
+struct Reinitialize<T: ?Sized + InitializableFromZeroed> {
+    ptr: *mut T,
+    size: usize,
+}
+
+impl<T: ?Sized + InitializableFromZeroed> Drop for Reinitialize<T> {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe {
+            ptr::write_bytes(self.ptr as *mut u8, 0, self.size);
+            hide_mem_impl::<T>(self.ptr);
+            T::initialize(self.ptr);
+        }
+    }
 }
 
 /// A type that can be initialized to a valid value, after being set to
@@ -79,6 +177,11 @@ pub trait InitializableFromZeroed {
     ///
     /// If all-bits-zero is a valid value for a place, this method can
     /// be left empty.
+    ///
+    /// # Safety
+    ///
+    /// The `place` must be valid for writes, and must not contain a live
+    /// value, since it will be overwritten without being dropped.
     unsafe fn initialize(place: *mut Self);
 }
 
@@ -105,7 +208,96 @@ impl InitializableFromZeroed for str {
     unsafe fn initialize(_place: *mut Self) {}
 }
 
+/// A slice of elements which are initialized with their `Default` value
+/// after being cleared.
+///
+/// A slice `[T]` can be cleared only if its elements are `ZeroSafe`.
+/// Wrapping it in a `DefaultSlice<T>` allows clearing a slice of
+/// elements which are not `ZeroSafe`, but implement `Default`: each
+/// element is dropped, the whole slice is set to all-bits-zero, and then
+/// each element is set to its default value.
+///
+/// With the `alloc` feature, a `Box<[T]>` can be converted to a
+/// `Box<DefaultSlice<T>>`, to be used as the place of a `ClearOnDrop`.
+///
+/// # Example
+///
+/// ```
+/// # use clear_on_drop::ClearOnDrop;
+/// # use clear_on_drop::clear::DefaultSlice;
+/// #[derive(Default)]
+/// struct RoundKey {
+///     key: Option<[u32; 4]>,
+/// }
+///
+/// let mut keys: Vec<RoundKey> = (0..10).map(|_| RoundKey::default()).collect();
+/// let mut keys = ClearOnDrop::new(DefaultSlice::from_mut(&mut keys));
+/// keys[0].key = Some([0x01234567; 4]);
+/// ```
+#[repr(transparent)]
+pub struct DefaultSlice<T>([T]);
+
+impl<T: Default> DefaultSlice<T> {
+    /// Converts a mutable slice to a mutable `DefaultSlice`.
+    #[inline]
+    pub fn from_mut(slice: &mut [T]) -> &mut Self {
+        // Same layout, since it's repr(transparent).
+        unsafe { &mut *(slice as *mut [T] as *mut Self) }
+    }
+
+    /// Converts a boxed slice to a boxed `DefaultSlice`.
+    #[cfg(feature = "alloc")]
+    #[inline]
+    pub fn from_box(slice: Box<[T]>) -> Box<Self> {
+        // Same layout, since it's repr(transparent).
+        unsafe { Box::from_raw(Box::into_raw(slice) as *mut Self) }
+    }
+}
+
+impl<T> Deref for DefaultSlice<T> {
+    type Target = [T];
+
+    #[inline]
+    fn deref(&self) -> &[T] {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for DefaultSlice<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.0
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for DefaultSlice<T> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl<T> InitializableFromZeroed for DefaultSlice<T>
+where
+    T: Default,
+{
+    #[inline]
+    unsafe fn initialize(place: *mut Self) {
+        let place = place as *mut [T];
+        // The elements are all-bits-zero, which is fine for MaybeUninit.
+        let len = (&*(place as *const [mem::MaybeUninit<T>])).len();
+        let ptr = place as *mut T;
+        for i in 0..len {
+            ptr::write(ptr.add(i), Default::default());
+        }
+    }
+}
+
 /// Unsafe trait to indicate which types are safe to set to all-bits-zero.
+///
+/// # Safety
+///
+/// All-bits-zero must be a valid value for the type.
 pub unsafe trait ZeroSafe {}
 
 // Yes, this is core::nonzero::Zeroable
@@ -121,24 +313,121 @@ unsafe impl ZeroSafe for i32 {}
 unsafe impl ZeroSafe for u32 {}
 unsafe impl ZeroSafe for i64 {}
 unsafe impl ZeroSafe for u64 {}
-#[cfg(feature = "nightly")]
 unsafe impl ZeroSafe for i128 {}
-#[cfg(feature = "nightly")]
 unsafe impl ZeroSafe for u128 {}
+unsafe impl ZeroSafe for f32 {}
+unsafe impl ZeroSafe for f64 {}
+unsafe impl ZeroSafe for bool {}
+unsafe impl ZeroSafe for char {}
+unsafe impl ZeroSafe for () {}
+
+// All-bits-zero is None
+unsafe impl<T: ?Sized> ZeroSafe for Option<NonNull<T>> {}
+unsafe impl<T: ?Sized> ZeroSafe for Option<&T> {}
+unsafe impl<T: ?Sized> ZeroSafe for Option<&mut T> {}
+unsafe impl ZeroSafe for Option<NonZeroIsize> {}
+unsafe impl ZeroSafe for Option<NonZeroUsize> {}
+unsafe impl ZeroSafe for Option<NonZeroI8> {}
+unsafe impl ZeroSafe for Option<NonZeroU8> {}
+unsafe impl ZeroSafe for Option<NonZeroI16> {}
+unsafe impl ZeroSafe for Option<NonZeroU16> {}
+unsafe impl ZeroSafe for Option<NonZeroI32> {}
+unsafe impl ZeroSafe for Option<NonZeroU32> {}
+unsafe impl ZeroSafe for Option<NonZeroI64> {}
+unsafe impl ZeroSafe for Option<NonZeroU64> {}
+unsafe impl ZeroSafe for Option<NonZeroI128> {}
+unsafe impl ZeroSafe for Option<NonZeroU128> {}
 
-macro_rules! array_impl_zerosafe {
-    ($($N:expr)+) => {
-        $(
-            unsafe impl<T: ZeroSafe> ZeroSafe for [T; $N] {}
-        )+
+// Same layout as the wrapped type
+unsafe impl<T: ZeroSafe> ZeroSafe for Wrapping<T> {}
+unsafe impl<T: ?Sized + ZeroSafe> ZeroSafe for Cell<T> {}
+unsafe impl<T: ?Sized + ZeroSafe> ZeroSafe for UnsafeCell<T> {}
+
+unsafe impl<T: ZeroSafe, const N: usize> ZeroSafe for [T; N] {}
+
+macro_rules! tuple_impl_zerosafe {
+    ($($T:ident)+) => {
+        unsafe impl<$($T: ZeroSafe),+> ZeroSafe for ($($T,)+) {}
     }
 }
 
-// Implement for fixed-size arrays of ZeroSafe up to 64
-array_impl_zerosafe!{
-     0  1  2  3  4  5  6  7  8  9 10 11 12 13 14 15
-    16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
-    32 33 34 35 36 37 38 39 40 41 42 43 44 45 46 47
-    48 49 50 51 52 53 54 55 56 57 58 59 60 61 62 63
-    64
+// Implement for tuples of ZeroSafe up to 12 elements
+tuple_impl_zerosafe!(A);
+tuple_impl_zerosafe!(A B);
+tuple_impl_zerosafe!(A B C);
+tuple_impl_zerosafe!(A B C D);
+tuple_impl_zerosafe!(A B C D E);
+tuple_impl_zerosafe!(A B C D E F);
+tuple_impl_zerosafe!(A B C D E F G);
+tuple_impl_zerosafe!(A B C D E F G H);
+tuple_impl_zerosafe!(A B C D E F G H I);
+tuple_impl_zerosafe!(A B C D E F G H I J);
+tuple_impl_zerosafe!(A B C D E F G H I J K);
+tuple_impl_zerosafe!(A B C D E F G H I J K L);
+
+#[cfg(test)]
+mod tests {
+    use super::ZeroSafe;
+    use core::cell::{Cell, UnsafeCell};
+    use core::num::{NonZeroU32, Wrapping};
+    use core::ptr::NonNull;
+
+    fn assert_zero_safe<T: ?Sized + ZeroSafe>() {}
+
+    #[test]
+    fn impls() {
+        assert_zero_safe::<u128>();
+        assert_zero_safe::<f64>();
+        assert_zero_safe::<bool>();
+        assert_zero_safe::<char>();
+        assert_zero_safe::<Option<NonNull<str>>>();
+        assert_zero_safe::<Option<&[u8]>>();
+        assert_zero_safe::<Option<&mut u8>>();
+        assert_zero_safe::<Option<NonZeroU32>>();
+        assert_zero_safe::<Wrapping<u32>>();
+        assert_zero_safe::<Cell<u32>>();
+        assert_zero_safe::<UnsafeCell<[u8; 4]>>();
+        assert_zero_safe::<[u8; 4096]>();
+        assert_zero_safe::<(u8, f32, [bool; 3])>();
+    }
+
+    #[test]
+    fn default_slice() {
+        use super::{Clear, DefaultSlice};
+
+        let mut place = [Some(Box::new(1)), None, Some(Box::new(3))];
+        DefaultSlice::from_mut(&mut place).clear();
+        assert!(place.iter().all(Option::is_none));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn default_slice_box() {
+        use super::{Clear, DefaultSlice};
+
+        let mut place = DefaultSlice::from_box(vec![String::from("secret"); 4].into_boxed_slice());
+        place.clear();
+        assert!(place.iter().all(String::is_empty));
+    }
+
+    #[test]
+    fn clear_with_panic() {
+        use super::Clear;
+        use crate::wipe::WipePattern;
+        use std::panic::{self, AssertUnwindSafe};
+
+        let mut place = [Some(Box::new(1)), None, Some(Box::new(3))];
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            place.clear_with(&WipePattern::Fill(&|_| panic!("pattern")));
+        }));
+        assert!(result.is_err());
+        assert!(place.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn clear_slice() {
+        let mut place = [(1.5f64, true, 'x'); 100];
+        crate::wipe::clear_slice(&mut place, crate::wipe::Stores::Regular);
+        assert!(place.iter().all(|&p| p == (0.0, false, '\0')));
+    }
 }
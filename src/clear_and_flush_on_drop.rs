@@ -0,0 +1,158 @@
+use core::fmt;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+
+use crate::clear::Clear;
+
+/// Zeroizes a storage location when dropped, and flushes it from the
+/// cache hierarchy.
+///
+/// This is the same as `ClearOnDrop`, except that the place is cleared
+/// with `Clear::clear_and_flush` instead of `Clear::clear`, so that the
+/// zeros reach main memory as soon as possible. See `wipe::flush` for
+/// details.
+///
+/// # Example
+///
+/// ```
+/// # use clear_on_drop::ClearAndFlushOnDrop;
+/// let mut place = [0u8; 32];
+/// {
+///     let mut key = ClearAndFlushOnDrop::new(&mut place);
+///     key.copy_from_slice(&[0x41; 32]);
+///     // ...
+/// }   // key is dropped here
+/// assert_eq!(place, [0; 32]);
+/// ```
+pub struct ClearAndFlushOnDrop<P>
+where
+    P: DerefMut,
+    P::Target: Clear,
+{
+    _place: P,
+}
+
+impl<P> ClearAndFlushOnDrop<P>
+where
+    P: DerefMut,
+    P::Target: Clear,
+{
+    /// Creates a new `ClearAndFlushOnDrop` which clears and flushes
+    /// `place` on drop.
+    ///
+    /// The same notes as for `ClearOnDrop::new` apply.
+    #[inline]
+    pub fn new(place: P) -> Self {
+        ClearAndFlushOnDrop { _place: place }
+    }
+
+    /// Consumes the `ClearAndFlushOnDrop`, returning the `place` after
+    /// clearing and flushing.
+    ///
+    /// Note: this is an associated function, which means that you have
+    /// to call it as `ClearAndFlushOnDrop::into_place(c)` instead of
+    /// `c.into_place()`. This is so that there is no conflict with a
+    /// method on the inner type.
+    #[inline]
+    pub fn into_place(mut c: Self) -> P {
+        c.clear_and_flush();
+        Self::into_uncleared_place(c)
+    }
+
+    /// Consumes the `ClearAndFlushOnDrop`, returning the `place` without
+    /// clearing.
+    ///
+    /// Note: this is an associated function, which means that you have
+    /// to call it as `ClearAndFlushOnDrop::into_uncleared_place(c)`
+    /// instead of `c.into_uncleared_place()`. This is so that there is
+    /// no conflict with a method on the inner type.
+    #[inline]
+    pub fn into_uncleared_place(c: Self) -> P {
+        unsafe {
+            let place = ptr::read(&c._place);
+            mem::forget(c);
+            place
+        }
+    }
+}
+
+impl<P> fmt::Debug for ClearAndFlushOnDrop<P>
+where
+    P: DerefMut + fmt::Debug,
+    P::Target: Clear,
+{
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self._place, f)
+    }
+}
+
+impl<P> Deref for ClearAndFlushOnDrop<P>
+where
+    P: DerefMut,
+    P::Target: Clear,
+{
+    type Target = P::Target;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        Deref::deref(&self._place)
+    }
+}
+
+impl<P> DerefMut for ClearAndFlushOnDrop<P>
+where
+    P: DerefMut,
+    P::Target: Clear,
+{
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        DerefMut::deref_mut(&mut self._place)
+    }
+}
+
+impl<P> Drop for ClearAndFlushOnDrop<P>
+where
+    P: DerefMut,
+    P::Target: Clear,
+{
+    #[inline]
+    fn drop(&mut self) {
+        self.clear_and_flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClearAndFlushOnDrop;
+
+    #[derive(Debug, Default)]
+    struct Place {
+        data: [u32; 4],
+    }
+
+    const DATA: [u32; 4] = [0x01234567, 0x89abcdef, 0xfedcba98, 0x76543210];
+
+    #[test]
+    fn on_stack() {
+        let mut place: Place = Default::default();
+        {
+            let mut clear = ClearAndFlushOnDrop::new(&mut place);
+            clear.data = DATA;
+            assert_eq!(clear.data, DATA);
+        }
+        assert_eq!(place.data, [0; 4]);
+    }
+
+    #[test]
+    fn into_box() {
+        let place: Box<Place> = Box::default();
+        let mut clear = ClearAndFlushOnDrop::new(place);
+        clear.data = DATA;
+        assert_eq!(clear.data, DATA);
+
+        let place = ClearAndFlushOnDrop::into_place(clear);
+        assert_eq!(place.data, [0; 4]);
+    }
+}
@@ -15,6 +15,10 @@ use crate::clear::Clear;
 /// similar). When this struct is dropped, the referenced location is
 /// overwritten with its `Default` value.
 ///
+/// Comparisons and hashing are forwarded to the place, so they can take
+/// a time which depends on its contents. Use `ConstantTimeEq::ct_eq`
+/// from the `constant_time` module, or a `Secret`, to compare secrets.
+///
 /// # Example
 ///
 /// ```
@@ -233,6 +237,8 @@ where
         PartialEq::eq(&self._place, &other._place)
     }
 
+    // Forwarded, since the place might implement it.
+    #[allow(clippy::partialeq_ne_impl)]
     #[inline]
     fn ne(&self, other: &ClearOnDrop<Q>) -> bool {
         PartialEq::ne(&self._place, &other._place)
@@ -313,6 +319,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::box_default)]
     fn on_box() {
         let place: Box<Place> = Box::new(Default::default());
         let mut clear = ClearOnDrop::new(place);
@@ -321,6 +328,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::box_default)]
     fn into_box() {
         let place: Box<Place> = Box::new(Default::default());
         let mut clear = ClearOnDrop::new(place);
@@ -332,6 +340,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::box_default)]
     fn into_uncleared_box() {
         let place: Box<Place> = Box::new(Default::default());
         let mut clear = ClearOnDrop::new(place);
@@ -0,0 +1,203 @@
+use core::future::Future;
+use core::mem::{self, MaybeUninit};
+use core::pin::Pin;
+use core::ptr;
+use core::task::{Context, Poll};
+
+use crate::clear_stack_on_return::clear_stack_on_return;
+use crate::hide::hide_mem;
+
+/// Polls a future with stack clearing, and clears its state when done.
+///
+/// An `async` block or function keeps its local variables in the state
+/// of its future, which lives wherever the future was pinned (usually
+/// on the heap, or on the executor's stack), and which is not cleared
+/// when the future completes or is dropped. Polling the future also
+/// uses the executor's stack, which is not cleared either.
+///
+/// A `ClearOnDropFuture<F>` polls the inner future through
+/// `clear_stack_on_return`, so `pages` pages of stack are overwritten
+/// after each call to `poll`. Once the inner future completes, or when
+/// it's dropped before completing, the inner future is dropped in place
+/// and its storage is overwritten with zeros.
+///
+/// # Example
+///
+/// ```
+/// # use clear_on_drop::ClearOnDropFuture;
+/// # async fn encrypt(input: &[u8]) -> Vec<u8> { input.to_owned() }
+/// let input = b"abc";
+/// let future = ClearOnDropFuture::new(1, encrypt(input));
+/// ```
+pub struct ClearOnDropFuture<F: Future> {
+    future: MaybeUninit<F>,
+    pages: usize,
+    done: bool,
+}
+
+impl<F: Future> ClearOnDropFuture<F> {
+    /// Wraps a future, clearing `pages` pages of stack after each poll.
+    #[inline]
+    pub fn new(pages: usize, future: F) -> Self {
+        ClearOnDropFuture {
+            future: MaybeUninit::new(future),
+            pages,
+            done: false,
+        }
+    }
+
+    /// Returns true if the inner future has completed.
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Drops the inner future in place and clears its storage.
+    #[inline]
+    fn clear_future(&mut self) {
+        self.done = true;
+        unsafe {
+            ptr::drop_in_place(self.future.as_mut_ptr());
+            ptr::write_bytes(self.future.as_mut_ptr() as *mut u8, 0, mem::size_of::<F>());
+        }
+        hide_mem(&mut self.future);
+    }
+}
+
+impl<F: Future> Future for ClearOnDropFuture<F> {
+    type Output = F::Output;
+
+    /// Polls the inner future, then clears the stack it used.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called again after the inner future has completed.
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<F::Output> {
+        // The inner future is never moved; it's dropped in place before
+        // its storage is reused or freed.
+        let this = unsafe { self.get_unchecked_mut() };
+        assert!(!this.done, "`ClearOnDropFuture` polled after completion");
+        let mut future = unsafe { Pin::new_unchecked(&mut *this.future.as_mut_ptr()) };
+        let poll = clear_stack_on_return(this.pages, || future.as_mut().poll(cx));
+        if poll.is_ready() {
+            this.clear_future();
+        }
+        poll
+    }
+}
+
+impl<F: Future> Drop for ClearOnDropFuture<F> {
+    #[inline]
+    fn drop(&mut self) {
+        if !self.done {
+            self.clear_future();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClearOnDropFuture;
+    use std::cell::Cell;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::rc::Rc;
+    use std::slice;
+    use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+    fn noop_waker() -> Waker {
+        const VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        fn clone(_: *const ()) -> RawWaker {
+            RawWaker::new(std::ptr::null(), &VTABLE)
+        }
+        fn noop(_: *const ()) {}
+        unsafe { Waker::from_raw(clone(std::ptr::null())) }
+    }
+
+    struct YieldOnce {
+        secret: [u8; 32],
+        yielded: bool,
+        drops: Rc<Cell<usize>>,
+    }
+
+    impl Future for YieldOnce {
+        type Output = u8;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<u8> {
+            if self.yielded {
+                Poll::Ready(self.secret[0])
+            } else {
+                self.yielded = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    impl Drop for YieldOnce {
+        fn drop(&mut self) {
+            self.drops.set(self.drops.get() + 1);
+        }
+    }
+
+    fn yield_once(drops: &Rc<Cell<usize>>) -> YieldOnce {
+        YieldOnce {
+            secret: [0x41; 32],
+            yielded: false,
+            drops: drops.clone(),
+        }
+    }
+
+    #[test]
+    fn ready() {
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(ClearOnDropFuture::new(1, async { 42 }));
+        assert_eq!(future.as_mut().poll(&mut cx), Poll::Ready(42));
+        assert!(future.is_done());
+    }
+
+    #[test]
+    fn cleared_when_done() {
+        let drops = Rc::new(Cell::new(0));
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(ClearOnDropFuture::new(1, yield_once(&drops)));
+        assert_eq!(future.as_mut().poll(&mut cx), Poll::Pending);
+        assert_eq!(drops.get(), 0);
+        assert_eq!(future.as_mut().poll(&mut cx), Poll::Ready(0x41));
+        assert_eq!(drops.get(), 1);
+
+        let storage = unsafe {
+            slice::from_raw_parts(
+                future.future.as_ptr() as *const u8,
+                std::mem::size_of::<YieldOnce>(),
+            )
+        };
+        assert!(storage.iter().all(|&b| b == 0));
+
+        drop(future);
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn dropped_when_pending() {
+        let drops = Rc::new(Cell::new(0));
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(ClearOnDropFuture::new(1, yield_once(&drops)));
+        assert_eq!(future.as_mut().poll(&mut cx), Poll::Pending);
+        drop(future);
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn polled_after_completion() {
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(ClearOnDropFuture::new(1, async {}));
+        let _ = future.as_mut().poll(&mut cx);
+        let _ = future.as_mut().poll(&mut cx);
+    }
+}
@@ -1,12 +1,17 @@
 use crate::fnoption::FnOption;
 use crate::hide::{hide_mem, hide_ptr};
+use crate::wipe::WipePattern;
 
 /// Calls a closure and overwrites its stack on return.
 ///
 /// This function calls `clear_stack` after calling the passed closure,
 /// taking care to prevent either of them being inlined, so the stack
 /// used by the closure will be overwritten with zeros (as long as a
-/// large enough number of `pages` is used).
+/// large enough number of pages is used).
+///
+/// The first argument is either the number of pages, or a
+/// `ClearStackOptions`, which can also ask for the registers used by
+/// the closure to be overwritten.
 ///
 /// For technical reasons, this function can be used only with `Fn` or
 /// `FnMut`. If all you have is a `FnOnce`, use the auxiliary function
@@ -21,11 +26,16 @@ use crate::hide::{hide_mem, hide_ptr};
 /// let result = clear_stack_on_return(1, || encrypt(input));
 /// ```
 #[inline]
-pub fn clear_stack_on_return<F, R>(pages: usize, mut f: F) -> R
+pub fn clear_stack_on_return<O, F, R>(options: O, mut f: F) -> R
 where
+    O: Into<ClearStackOptions>,
     F: FnMut() -> R,
 {
-    let _clear = ClearStackOnDrop { pages };
+    let options = options.into();
+    let _clear = ClearStackOnDrop {
+        pages: options.pages,
+        registers: options.registers,
+    };
     // Do not inline f to make sure clear_stack uses the same stack space.
     hide_ptr::<&mut dyn FnMut() -> R>(&mut f)()
 }
@@ -44,16 +54,64 @@ where
 /// let result = clear_stack_on_return_fnonce(1, || encrypt(input));
 /// ```
 #[inline]
-pub fn clear_stack_on_return_fnonce<F, R>(pages: usize, f: F) -> R
+pub fn clear_stack_on_return_fnonce<O, F, R>(options: O, f: F) -> R
 where
+    O: Into<ClearStackOptions>,
     F: FnOnce() -> R,
 {
     let mut f = FnOption::new(f);
-    clear_stack_on_return(pages, || f.call_mut()).unwrap()
+    clear_stack_on_return(options, || f.call_mut()).unwrap()
 }
 
-struct ClearStackOnDrop {
+/// What `clear_stack_on_return` overwrites after the closure returns.
+///
+/// A number of pages converts into options which overwrite only that
+/// many pages of stack; `registers` makes it also call
+/// `clear_registers`, so the scratch registers used by the closure
+/// (including the vector registers) are overwritten with zeros too.
+///
+/// # Example
+///
+/// ```
+/// # use clear_on_drop::{clear_stack_on_return, ClearStackOptions};
+/// # fn encrypt(input: &[u8]) -> Vec<u8> { input.to_owned() }
+/// let input = b"abc";
+/// let options = ClearStackOptions::new(1).registers(true);
+/// let result = clear_stack_on_return(options, || encrypt(input));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ClearStackOptions {
     pages: usize,
+    registers: bool,
+}
+
+impl ClearStackOptions {
+    /// Overwrites `pages` 4096-byte blocks of the stack, and no registers.
+    #[inline]
+    pub const fn new(pages: usize) -> Self {
+        ClearStackOptions {
+            pages,
+            registers: false,
+        }
+    }
+
+    /// Sets whether the registers are overwritten after the stack.
+    #[inline]
+    pub const fn registers(self, registers: bool) -> Self {
+        ClearStackOptions { registers, ..self }
+    }
+}
+
+impl From<usize> for ClearStackOptions {
+    #[inline]
+    fn from(pages: usize) -> Self {
+        ClearStackOptions::new(pages)
+    }
+}
+
+pub(crate) struct ClearStackOnDrop {
+    pub(crate) pages: usize,
+    pub(crate) registers: bool,
 }
 
 impl Drop for ClearStackOnDrop {
@@ -61,18 +119,481 @@ impl Drop for ClearStackOnDrop {
     fn drop(&mut self) {
         // Do not inline clear_stack.
         hide_ptr::<fn(usize)>(clear_stack)(self.pages);
+        if self.registers {
+            hide_ptr::<fn()>(clear_registers)();
+        }
     }
 }
 
+/// Overwrites the scratch registers with zeros.
+///
+/// Registers which a function may change without restoring them
+/// (caller-saved, or volatile, registers) can keep values computed by
+/// the function after it returns. This function overwrites them: on
+/// x86-64, the general-purpose scratch registers and all the vector
+/// registers (`xmm` and `ymm`, and with AVX-512 `zmm` and the `k` mask
+/// registers); on AArch64, the general-purpose registers `x0` to `x17`
+/// and all of the NEON `v` registers (including the upper halves of
+/// `v8` to `v15`, whose lower halves are restored on return).
+///
+/// Callee-saved registers are not overwritten, since they are restored
+/// to the caller's values before a function returns.
+///
+/// On other architectures, this function does nothing.
+///
+/// # Example
+///
+/// ```
+/// # use clear_on_drop::clear_registers;
+/// clear_registers();
+/// ```
+#[inline]
+pub fn clear_registers() {
+    regs::clear_registers()
+}
+
 /// Overwrites a few pages of stack.
 ///
 /// This function will overwrite `pages` 4096-byte blocks of the stack
 /// with zeros.
+#[inline]
 pub fn clear_stack(pages: usize) {
+    clear_stack_bytes(pages.saturating_mul(4096));
+}
+
+/// Overwrites some bytes of stack.
+///
+/// This function will overwrite at least `bytes` bytes of the stack,
+/// below the current stack frame, with zeros.
+///
+/// On x86-64 and AArch64, this is done in a single loop which moves the
+/// stack pointer down, writing zeros as it goes, and then restores it;
+/// the amount of stack is rounded up to a multiple of 64 bytes. On other
+/// architectures, it's done through recursive calls, each overwriting a
+/// block of 4096 bytes, so the amount of stack is rounded up to a
+/// multiple of that.
+///
+/// # Example
+///
+/// ```
+/// # use clear_on_drop::clear_stack_bytes;
+/// clear_stack_bytes(1000);
+/// ```
+#[inline]
+pub fn clear_stack_bytes(bytes: usize) {
+    if bytes > 0 {
+        imp::clear_stack_bytes(bytes)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod imp {
+    use core::arch::asm;
+
+    #[inline(never)]
+    pub fn clear_stack_bytes(bytes: usize) {
+        let total = (bytes + 63) / 64 * 64;
+        let first = match total % 4096 {
+            0 => 4096,
+            rem => rem,
+        };
+        // Moves down one page at a time, so that each page is touched in
+        // order, as expected by the stack guard page mechanisms.
+        unsafe {
+            asm!(
+                "mov {saved}, rsp",
+                "and rsp, -16",
+                "xor eax, eax",
+                "2:",
+                "mov rcx, {chunk}",
+                "sub rsp, rcx",
+                "mov rdi, rsp",
+                "rep stosb",
+                "sub {total}, {chunk}",
+                "mov {chunk}, 4096",
+                "jnz 2b",
+                "mov rsp, {saved}",
+                saved = out(reg) _,
+                total = inout(reg) total => _,
+                chunk = inout(reg) first => _,
+                out("rax") _,
+                out("rcx") _,
+                out("rdi") _,
+            );
+        }
+    }
+
+    #[inline(never)]
+    pub fn fill_stack_bytes(bytes: usize, block: &[u8; 4096]) {
+        let total = (bytes + 63) / 64 * 64;
+        let first = match total % 4096 {
+            0 => 4096,
+            rem => rem,
+        };
+        // The same loop as above, copying the block to each page.
+        unsafe {
+            asm!(
+                "mov {saved}, rsp",
+                "and rsp, -16",
+                "2:",
+                "mov rcx, {chunk}",
+                "sub rsp, rcx",
+                "mov rdi, rsp",
+                "mov rsi, {block}",
+                "rep movsb",
+                "sub {total}, {chunk}",
+                "mov {chunk}, 4096",
+                "jnz 2b",
+                "mov rsp, {saved}",
+                saved = out(reg) _,
+                total = inout(reg) total => _,
+                chunk = inout(reg) first => _,
+                block = in(reg) block.as_ptr(),
+                out("rcx") _,
+                out("rdi") _,
+                out("rsi") _,
+            );
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod imp {
+    use core::arch::asm;
+
+    #[inline(never)]
+    pub fn clear_stack_bytes(bytes: usize) {
+        let blocks = (bytes + 63) / 64;
+        // Writes from the top down, so that each page is touched in
+        // order, as expected by the stack guard page mechanisms.
+        unsafe {
+            asm!(
+                "mov {saved}, sp",
+                "movi {zero:v}.16b, #0",
+                "2:",
+                "stp {zero:q}, {zero:q}, [sp, #-32]!",
+                "stp {zero:q}, {zero:q}, [sp, #-32]!",
+                "subs {blocks}, {blocks}, #1",
+                "b.ne 2b",
+                "mov sp, {saved}",
+                saved = out(reg) _,
+                blocks = inout(reg) blocks => _,
+                zero = out(vreg) _,
+            );
+        }
+    }
+
+    #[inline(never)]
+    pub fn fill_stack_bytes(bytes: usize, block: &[u8; 4096]) {
+        let blocks = (bytes + 63) / 64;
+        // The same loop as above, copying the block backwards, and
+        // wrapping around to its end.
+        unsafe {
+            asm!(
+                "mov {saved}, sp",
+                "add {end}, {block}, #4096",
+                "mov {src}, {end}",
+                "2:",
+                "cmp {src}, {block}",
+                "csel {src}, {end}, {src}, eq",
+                "ldp {a:q}, {b:q}, [{src}, #-32]",
+                "ldp {c:q}, {d:q}, [{src}, #-64]",
+                "sub {src}, {src}, #64",
+                "stp {a:q}, {b:q}, [sp, #-32]!",
+                "stp {c:q}, {d:q}, [sp, #-32]!",
+                "subs {blocks}, {blocks}, #1",
+                "b.ne 2b",
+                "mov sp, {saved}",
+                saved = out(reg) _,
+                end = out(reg) _,
+                src = out(reg) _,
+                blocks = inout(reg) blocks => _,
+                block = in(reg) block.as_ptr(),
+                a = out(vreg) _,
+                b = out(vreg) _,
+                c = out(vreg) _,
+                d = out(vreg) _,
+            );
+        }
+    }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod imp {
+    use crate::hide::hide_mem;
+
+    #[inline]
+    pub fn clear_stack_bytes(bytes: usize) {
+        clear_stack_blocks((bytes + 4095) / 4096)
+    }
+
+    fn clear_stack_blocks(blocks: usize) {
+        if blocks > 0 {
+            let mut buf = [0u8; 4096];
+            hide_mem(&mut buf); // prevent moving after recursive call
+            clear_stack_blocks(blocks - 1);
+            hide_mem(&mut buf); // prevent reuse of stack space for call
+        }
+    }
+
+    #[inline(never)]
+    pub fn fill_stack_bytes(bytes: usize, block: &[u8; 4096]) {
+        fill_stack_blocks((bytes + 4095) / 4096, block)
+    }
+
+    fn fill_stack_blocks(blocks: usize, block: &[u8; 4096]) {
+        if blocks > 0 {
+            let mut buf = *block;
+            hide_mem(&mut buf); // prevent moving after recursive call
+            fill_stack_blocks(blocks - 1, block);
+            hide_mem(&mut buf); // prevent reuse of stack space for call
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod regs {
+    use core::arch::asm;
+
+    #[inline(never)]
+    pub fn clear_registers() {
+        unsafe {
+            if has_avx512f() {
+                clear_vector_registers_avx512f();
+            } else if has_avx() {
+                clear_vector_registers_avx();
+            } else {
+                clear_vector_registers_sse();
+            }
+            // The System V ABI has the most scratch registers; using it
+            // also on Windows makes the compiler preserve the ones which
+            // are callee-saved there.
+            asm!(
+                "xor eax, eax",
+                "xor ecx, ecx",
+                "xor edx, edx",
+                "xor esi, esi",
+                "xor edi, edi",
+                "xor r8d, r8d",
+                "xor r9d, r9d",
+                "xor r10d, r10d",
+                "xor r11d, r11d",
+                clobber_abi("sysv64"),
+                options(nostack, nomem),
+            );
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    fn has_avx512f() -> bool {
+        is_x86_feature_detected!("avx512f")
+    }
+
+    #[cfg(not(feature = "std"))]
+    #[inline]
+    fn has_avx512f() -> bool {
+        cfg!(target_feature = "avx512f")
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    fn has_avx() -> bool {
+        is_x86_feature_detected!("avx")
+    }
+
+    #[cfg(not(feature = "std"))]
+    #[inline]
+    fn has_avx() -> bool {
+        cfg!(target_feature = "avx")
+    }
+
+    // `#[target_feature(enable = "avx512f")]` is only accepted since
+    // Rust 1.89; with older compilers, code outside of a crate built
+    // for AVX-512 can't use zmm16 to zmm31 or k0 to k7, so there's
+    // nothing for the clobbers to preserve without it.
+    #[cfg_attr(clear_on_drop_avx512f, target_feature(enable = "avx512f"))]
+    unsafe fn clear_vector_registers_avx512f() {
+        asm!(
+            "vzeroall",
+            "vpxord zmm16, zmm16, zmm16",
+            "vpxord zmm17, zmm17, zmm17",
+            "vpxord zmm18, zmm18, zmm18",
+            "vpxord zmm19, zmm19, zmm19",
+            "vpxord zmm20, zmm20, zmm20",
+            "vpxord zmm21, zmm21, zmm21",
+            "vpxord zmm22, zmm22, zmm22",
+            "vpxord zmm23, zmm23, zmm23",
+            "vpxord zmm24, zmm24, zmm24",
+            "vpxord zmm25, zmm25, zmm25",
+            "vpxord zmm26, zmm26, zmm26",
+            "vpxord zmm27, zmm27, zmm27",
+            "vpxord zmm28, zmm28, zmm28",
+            "vpxord zmm29, zmm29, zmm29",
+            "vpxord zmm30, zmm30, zmm30",
+            "vpxord zmm31, zmm31, zmm31",
+            "kxorw k0, k0, k0",
+            "kxorw k1, k1, k1",
+            "kxorw k2, k2, k2",
+            "kxorw k3, k3, k3",
+            "kxorw k4, k4, k4",
+            "kxorw k5, k5, k5",
+            "kxorw k6, k6, k6",
+            "kxorw k7, k7, k7",
+            clobber_abi("sysv64"),
+            options(nostack, nomem),
+        );
+    }
+
+    #[target_feature(enable = "avx")]
+    unsafe fn clear_vector_registers_avx() {
+        asm!("vzeroall", clobber_abi("sysv64"), options(nostack, nomem));
+    }
+
+    unsafe fn clear_vector_registers_sse() {
+        asm!(
+            "xorps xmm0, xmm0",
+            "xorps xmm1, xmm1",
+            "xorps xmm2, xmm2",
+            "xorps xmm3, xmm3",
+            "xorps xmm4, xmm4",
+            "xorps xmm5, xmm5",
+            "xorps xmm6, xmm6",
+            "xorps xmm7, xmm7",
+            "xorps xmm8, xmm8",
+            "xorps xmm9, xmm9",
+            "xorps xmm10, xmm10",
+            "xorps xmm11, xmm11",
+            "xorps xmm12, xmm12",
+            "xorps xmm13, xmm13",
+            "xorps xmm14, xmm14",
+            "xorps xmm15, xmm15",
+            clobber_abi("sysv64"),
+            options(nostack, nomem),
+        );
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod regs {
+    use core::arch::asm;
+
+    #[inline(never)]
+    pub fn clear_registers() {
+        // v8 to v15 are marked as clobbered explicitly, so only their
+        // lower halves are restored on return; x18 is reserved for the
+        // platform, and left alone.
+        unsafe {
+            asm!(
+                "mov x0, xzr",
+                "mov x1, xzr",
+                "mov x2, xzr",
+                "mov x3, xzr",
+                "mov x4, xzr",
+                "mov x5, xzr",
+                "mov x6, xzr",
+                "mov x7, xzr",
+                "mov x8, xzr",
+                "mov x9, xzr",
+                "mov x10, xzr",
+                "mov x11, xzr",
+                "mov x12, xzr",
+                "mov x13, xzr",
+                "mov x14, xzr",
+                "mov x15, xzr",
+                "mov x16, xzr",
+                "mov x17, xzr",
+                "movi v0.2d, #0",
+                "movi v1.2d, #0",
+                "movi v2.2d, #0",
+                "movi v3.2d, #0",
+                "movi v4.2d, #0",
+                "movi v5.2d, #0",
+                "movi v6.2d, #0",
+                "movi v7.2d, #0",
+                "movi v8.2d, #0",
+                "movi v9.2d, #0",
+                "movi v10.2d, #0",
+                "movi v11.2d, #0",
+                "movi v12.2d, #0",
+                "movi v13.2d, #0",
+                "movi v14.2d, #0",
+                "movi v15.2d, #0",
+                "movi v16.2d, #0",
+                "movi v17.2d, #0",
+                "movi v18.2d, #0",
+                "movi v19.2d, #0",
+                "movi v20.2d, #0",
+                "movi v21.2d, #0",
+                "movi v22.2d, #0",
+                "movi v23.2d, #0",
+                "movi v24.2d, #0",
+                "movi v25.2d, #0",
+                "movi v26.2d, #0",
+                "movi v27.2d, #0",
+                "movi v28.2d, #0",
+                "movi v29.2d, #0",
+                "movi v30.2d, #0",
+                "movi v31.2d, #0",
+                out("v8") _,
+                out("v9") _,
+                out("v10") _,
+                out("v11") _,
+                out("v12") _,
+                out("v13") _,
+                out("v14") _,
+                out("v15") _,
+                clobber_abi("C"),
+                options(nostack, nomem),
+            );
+        }
+    }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod regs {
+    #[inline]
+    pub fn clear_registers() {}
+}
+
+/// Overwrites a few pages of stack with a pattern, then with zeros.
+///
+/// This function is a variant of `clear_stack` which overwrites the
+/// stack with each pass of `pattern` before setting it to zero, in the
+/// same way as `clear_stack_bytes`. Each pass is generated once, into a
+/// block of 4096 bytes, which is then copied to each page of the stack.
+///
+/// # Example
+///
+/// ```
+/// # use clear_on_drop::clear_stack_with;
+/// # use clear_on_drop::wipe::WipePattern;
+/// clear_stack_with(2, &WipePattern::Byte(0xaa));
+/// ```
+pub fn clear_stack_with(pages: usize, pattern: &WipePattern) {
     if pages > 0 {
-        let mut buf = [0u8; 4096];
-        hide_mem(&mut buf); // prevent moving after recursive call
-        clear_stack(pages - 1);
-        hide_mem(&mut buf); // prevent reuse of stack space for call
+        // Do not inline overwrite_stack.
+        hide_ptr::<fn(usize, &WipePattern)>(overwrite_stack)(pages.saturating_mul(4096), pattern);
+    }
+}
+
+/// Overwrites `bytes` bytes of stack with each pass of `pattern`, and
+/// then with zeros.
+///
+/// Every pass is written from this function's frame, so that all of
+/// them overwrite the same area.
+#[inline(never)]
+fn overwrite_stack(bytes: usize, pattern: &WipePattern) {
+    let mut block = [0u8; 4096];
+    let mut n = 0;
+    while let Ok(pass) = pattern.nth_pass(n) {
+        unsafe {
+            pass.overwrite(block.as_mut_ptr(), block.len());
+        }
+        imp::fill_stack_bytes(bytes, &block);
+        n += 1;
     }
+    block = [0u8; 4096];
+    hide_mem(&mut block); // prevent eliding the zeros
+    imp::fill_stack_bytes(bytes, &block);
 }
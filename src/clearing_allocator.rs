@@ -0,0 +1,134 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::cmp;
+use core::ptr;
+
+use crate::hide::hide_mem_impl;
+
+/// A memory allocator which overwrites all memory with zeros before
+/// freeing it.
+///
+/// `ClearOnDrop` clears only what it wraps; memory freed elsewhere, in
+/// particular the old buffers left behind when a `Vec` or `String` grows,
+/// can still contain copies of sensitive data. This allocator wraps
+/// another allocator, and clears every block before passing it to the
+/// wrapped allocator to be freed.
+///
+/// Reallocations never grow or shrink a block in place; a new block is
+/// always allocated, and the old one is cleared and freed after its
+/// contents have been copied to the new one. This makes reallocation
+/// slower, but guarantees no copy is left behind.
+///
+/// # Example
+///
+/// ```
+/// # use std::alloc::System;
+/// # use clear_on_drop::ClearingAllocator;
+/// #[global_allocator]
+/// static ALLOCATOR: ClearingAllocator<System> = ClearingAllocator::new(System);
+///
+/// let mut v = Vec::new();
+/// for i in 0..1000 {
+///     v.push(i); // old buffers are cleared when the vector grows
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct ClearingAllocator<A> {
+    inner: A,
+}
+
+impl<A> ClearingAllocator<A> {
+    /// Creates a new `ClearingAllocator` wrapping the `inner` allocator.
+    #[inline]
+    pub const fn new(inner: A) -> Self {
+        ClearingAllocator { inner }
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for ClearingAllocator<A> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.inner.alloc(layout)
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.inner.alloc_zeroed(layout)
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let size = layout.size();
+        ptr::write_bytes(ptr, 0, size);
+        hide_mem_impl::<[u8]>(ptr::slice_from_raw_parts_mut(ptr, size));
+        self.inner.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new_ptr = self.inner.alloc(new_layout);
+        if !new_ptr.is_null() {
+            ptr::copy_nonoverlapping(ptr, new_ptr, cmp::min(layout.size(), new_size));
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClearingAllocator;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::slice;
+
+    /// Checks that every block is cleared before being freed.
+    struct CheckingAllocator;
+
+    unsafe impl GlobalAlloc for CheckingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            assert!(slice::from_raw_parts(ptr, layout.size())
+                .iter()
+                .all(|&b| b == 0));
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    static ALLOCATOR: ClearingAllocator<CheckingAllocator> =
+        ClearingAllocator::new(CheckingAllocator);
+
+    #[test]
+    fn dealloc() {
+        unsafe {
+            let layout = Layout::from_size_align(64, 8).unwrap();
+            let ptr = ALLOCATOR.alloc(layout);
+            assert!(!ptr.is_null());
+            ptr.write_bytes(0x41, 64);
+            ALLOCATOR.dealloc(ptr, layout);
+        }
+    }
+
+    #[test]
+    fn realloc() {
+        unsafe {
+            let layout = Layout::from_size_align(16, 8).unwrap();
+            let ptr = ALLOCATOR.alloc(layout);
+            assert!(!ptr.is_null());
+            ptr.write_bytes(0x41, 16);
+
+            let new_ptr = ALLOCATOR.realloc(ptr, layout, 4096);
+            assert!(!new_ptr.is_null());
+            assert_ne!(new_ptr, ptr);
+            assert!(slice::from_raw_parts(new_ptr, 16)
+                .iter()
+                .all(|&b| b == 0x41));
+
+            let new_layout = Layout::from_size_align(4096, 8).unwrap();
+            let ptr = ALLOCATOR.realloc(new_ptr, new_layout, 8);
+            assert!(slice::from_raw_parts(ptr, 8).iter().all(|&b| b == 0x41));
+            ALLOCATOR.dealloc(ptr, Layout::from_size_align(8, 8).unwrap());
+        }
+    }
+}
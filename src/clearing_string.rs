@@ -0,0 +1,201 @@
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::str::{self, Utf8Error};
+
+use crate::clearing_vec::ClearingVec;
+
+/// A growable string which never leaves copies of its contents behind.
+///
+/// This is the string equivalent of `ClearingVec<u8>`: the old buffer is
+/// cleared when the string grows, the vacated bytes are cleared when
+/// the string shrinks, and the whole buffer is cleared when dropped.
+///
+/// # Example
+///
+/// ```
+/// # use clear_on_drop::ClearingString;
+/// let mut password = ClearingString::new();
+/// password.push_str("correct horse");
+/// password.push_str(" battery staple");
+/// assert_eq!(&password[..], "correct horse battery staple");
+/// ```
+#[derive(Default)]
+pub struct ClearingString {
+    vec: ClearingVec<u8>,
+}
+
+impl ClearingString {
+    /// Creates a new, empty `ClearingString`.
+    #[inline]
+    pub fn new() -> Self {
+        ClearingString {
+            vec: ClearingVec::new(),
+        }
+    }
+
+    /// Creates a new, empty `ClearingString` with space for at least
+    /// `capacity` bytes.
+    ///
+    /// Allocating enough capacity up front avoids copying the contents
+    /// when the string grows.
+    #[inline]
+    pub fn with_capacity(capacity: usize) -> Self {
+        ClearingString {
+            vec: ClearingVec::with_capacity(capacity),
+        }
+    }
+
+    /// Converts a vector of bytes to a `ClearingString`.
+    ///
+    /// If the bytes are not valid UTF-8, the vector is dropped (and
+    /// therefore cleared), and the error is returned.
+    #[inline]
+    pub fn from_utf8(vec: ClearingVec<u8>) -> Result<Self, Utf8Error> {
+        str::from_utf8(&vec)?;
+        Ok(ClearingString { vec })
+    }
+
+    /// Returns the number of bytes the string can hold without
+    /// reallocating.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.vec.capacity()
+    }
+
+    /// Reserves capacity for at least `additional` more bytes.
+    ///
+    /// If the string has to be moved to a larger buffer, the old buffer
+    /// is cleared.
+    #[inline]
+    pub fn reserve(&mut self, additional: usize) {
+        self.vec.reserve(additional)
+    }
+
+    /// Appends a character to the end of the string.
+    #[inline]
+    pub fn push(&mut self, ch: char) {
+        self.push_str(ch.encode_utf8(&mut [0; 4]))
+    }
+
+    /// Appends a string slice to the end of the string.
+    #[inline]
+    pub fn push_str(&mut self, s: &str) {
+        self.vec.extend_from_slice(s.as_bytes())
+    }
+
+    /// Removes the last character of the string and returns it, clearing
+    /// its bytes in the buffer.
+    #[inline]
+    pub fn pop(&mut self) -> Option<char> {
+        let ch = self.chars().next_back()?;
+        let len = self.len() - ch.len_utf8();
+        self.vec.truncate(len);
+        Some(ch)
+    }
+
+    /// Shortens the string to `len` bytes, clearing the rest. Has no
+    /// effect if the string is already shorter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` does not lie on a `char` boundary.
+    #[inline]
+    pub fn truncate(&mut self, len: usize) {
+        if len <= self.len() {
+            assert!(self.is_char_boundary(len));
+            self.vec.truncate(len)
+        }
+    }
+
+    /// Removes all contents of the string, clearing the buffer.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.vec.clear()
+    }
+
+    /// Returns the contents of the string as a string slice.
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        unsafe { str::from_utf8_unchecked(&self.vec) }
+    }
+
+    /// Returns the contents of the string as a mutable string slice.
+    #[inline]
+    pub fn as_mut_str(&mut self) -> &mut str {
+        unsafe { str::from_utf8_unchecked_mut(&mut self.vec) }
+    }
+}
+
+impl Deref for ClearingString {
+    type Target = str;
+
+    #[inline]
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl DerefMut for ClearingString {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut str {
+        self.as_mut_str()
+    }
+}
+
+impl fmt::Debug for ClearingString {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Write for ClearingString {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClearingString;
+    use crate::ClearingVec;
+
+    #[test]
+    fn push() {
+        let mut s = ClearingString::new();
+        s.push_str("test");
+        s.push('\u{e9}');
+        assert_eq!(&s[..], "test\u{e9}");
+    }
+
+    #[test]
+    fn pop() {
+        let mut s = ClearingString::with_capacity(8);
+        s.push_str("test\u{e9}");
+        assert_eq!(s.pop(), Some('\u{e9}'));
+        assert_eq!(&s[..], "test");
+        let spare = unsafe { std::slice::from_raw_parts(s.as_ptr().add(4), 2) };
+        assert_eq!(spare, [0; 2]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn truncate_inside_char() {
+        let mut s = ClearingString::new();
+        s.push('\u{e9}');
+        s.truncate(1);
+    }
+
+    #[test]
+    fn from_utf8() {
+        let mut v = ClearingVec::new();
+        v.extend_from_slice(b"test");
+        assert_eq!(&ClearingString::from_utf8(v).unwrap()[..], "test");
+
+        let mut v = ClearingVec::new();
+        v.extend_from_slice(b"\xff");
+        assert!(ClearingString::from_utf8(v).is_err());
+    }
+}
@@ -0,0 +1,252 @@
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+
+use crate::clear::ZeroSafe;
+use crate::hide::hide_mem_impl;
+
+/// A growable array which never leaves copies of its contents behind.
+///
+/// A `Vec<T>` moves its contents to a new buffer when it grows, leaving
+/// a copy in the old buffer, which is freed without being cleared.
+/// Similarly, removing elements leaves their values in the vacated
+/// slots of the buffer.
+///
+/// A `ClearingVec<T>` instead clears the old buffer after moving its
+/// contents to the new one, clears the vacated slots whenever elements
+/// are removed, and clears the whole buffer (including any unused
+/// capacity) when dropped.
+///
+/// The elements must be `ZeroSafe`, so that the buffer can be cleared
+/// to all-bits-zero.
+///
+/// # Example
+///
+/// ```
+/// # use clear_on_drop::ClearingVec;
+/// let mut key = ClearingVec::new();
+/// for b in b"secret" {
+///     key.push(*b); // old buffers are cleared when the vector grows
+/// }
+/// assert_eq!(&key[..], b"secret");
+/// ```
+pub struct ClearingVec<T: ZeroSafe> {
+    buf: Vec<T>,
+}
+
+impl<T: ZeroSafe> ClearingVec<T> {
+    /// Creates a new, empty `ClearingVec<T>`.
+    #[inline]
+    pub fn new() -> Self {
+        ClearingVec { buf: Vec::new() }
+    }
+
+    /// Creates a new, empty `ClearingVec<T>` with space for at least
+    /// `capacity` elements.
+    ///
+    /// Allocating enough capacity up front avoids copying the contents
+    /// when the vector grows.
+    #[inline]
+    pub fn with_capacity(capacity: usize) -> Self {
+        ClearingVec {
+            buf: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the number of elements the vector can hold without
+    /// reallocating.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    /// Reserves capacity for at least `additional` more elements.
+    ///
+    /// If the vector has to be moved to a larger buffer, the old buffer
+    /// is cleared.
+    pub fn reserve(&mut self, additional: usize) {
+        let len = self.buf.len();
+        let required = len.checked_add(additional).expect("capacity overflow");
+        if required > self.buf.capacity() {
+            let capacity = required.max(self.buf.capacity() * 2).max(4);
+            let mut buf = Vec::with_capacity(capacity);
+            unsafe {
+                ptr::copy_nonoverlapping(self.buf.as_ptr(), buf.as_mut_ptr(), len);
+                // The elements were moved to the new buffer.
+                self.buf.set_len(0);
+                buf.set_len(len);
+            }
+            let mut old = mem::replace(&mut self.buf, buf);
+            clear_spare(&mut old, 0);
+        }
+    }
+
+    /// Appends an element to the end of the vector.
+    #[inline]
+    pub fn push(&mut self, value: T) {
+        if self.buf.len() == self.buf.capacity() {
+            self.reserve(1);
+        }
+        // Does not reallocate, since there is enough capacity.
+        self.buf.push(value);
+    }
+
+    /// Removes the last element of the vector and returns it, clearing
+    /// its slot in the buffer.
+    #[inline]
+    pub fn pop(&mut self) -> Option<T> {
+        let old_len = self.buf.len();
+        let value = self.buf.pop();
+        let len = self.buf.len();
+        clear_range(&mut self.buf, len, old_len);
+        value
+    }
+
+    /// Shortens the vector to `len` elements, dropping and clearing the
+    /// rest. Has no effect if the vector is already shorter.
+    #[inline]
+    pub fn truncate(&mut self, len: usize) {
+        // Only the vacated elements have to be cleared; the rest of the
+        // spare capacity was either cleared before, or never used.
+        let old_len = self.buf.len();
+        self.buf.truncate(len);
+        let len = self.buf.len();
+        clear_range(&mut self.buf, len, old_len);
+    }
+
+    /// Removes all elements of the vector, clearing the buffer.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Returns the contents of the vector as a slice.
+    #[inline]
+    pub fn as_slice(&self) -> &[T] {
+        &self.buf
+    }
+
+    /// Returns the contents of the vector as a mutable slice.
+    #[inline]
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.buf
+    }
+}
+
+impl<T: ZeroSafe + Clone> ClearingVec<T> {
+    /// Clones and appends all elements of `other` to the vector.
+    pub fn extend_from_slice(&mut self, other: &[T]) {
+        self.reserve(other.len());
+        // Does not reallocate, since there is enough capacity.
+        self.buf.extend_from_slice(other);
+    }
+}
+
+/// Drops nothing, but clears the buffer from `from` to its capacity.
+#[inline]
+pub(crate) fn clear_spare<T>(buf: &mut Vec<T>, from: usize) {
+    let capacity = buf.capacity();
+    clear_range(buf, from, capacity);
+}
+
+/// Drops nothing, but clears the buffer from `from` to `to`, which must
+/// not be past its capacity.
+#[inline]
+fn clear_range<T>(buf: &mut Vec<T>, from: usize, to: usize) {
+    debug_assert!(from <= to && to <= buf.capacity());
+    unsafe {
+        let ptr = buf.as_mut_ptr().add(from) as *mut u8;
+        let size = (to - from) * mem::size_of::<T>();
+        ptr::write_bytes(ptr, 0, size);
+        hide_mem_impl::<[u8]>(ptr::slice_from_raw_parts_mut(ptr, size));
+    }
+}
+
+impl<T: ZeroSafe> Default for ClearingVec<T> {
+    #[inline]
+    fn default() -> Self {
+        ClearingVec::new()
+    }
+}
+
+impl<T: ZeroSafe> Deref for ClearingVec<T> {
+    type Target = [T];
+
+    #[inline]
+    fn deref(&self) -> &[T] {
+        &self.buf
+    }
+}
+
+impl<T: ZeroSafe> DerefMut for ClearingVec<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.buf
+    }
+}
+
+impl<T: ZeroSafe + fmt::Debug> fmt::Debug for ClearingVec<T> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.buf, f)
+    }
+}
+
+impl<T: ZeroSafe> Drop for ClearingVec<T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClearingVec;
+    use std::slice;
+
+    fn spare(v: &ClearingVec<u32>) -> &[u32] {
+        unsafe { slice::from_raw_parts(v.as_ptr().add(v.len()), v.capacity() - v.len()) }
+    }
+
+    #[test]
+    fn push() {
+        let mut v = ClearingVec::new();
+        for i in 0..100u32 {
+            v.push(i);
+        }
+        assert_eq!(v.len(), 100);
+        assert!(v.iter().copied().eq(0..100));
+    }
+
+    #[test]
+    fn extend_from_slice() {
+        let mut v = ClearingVec::with_capacity(1);
+        v.extend_from_slice(&[1u32, 2, 3]);
+        v.extend_from_slice(&[4, 5]);
+        assert_eq!(&v[..], [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn pop() {
+        let mut v = ClearingVec::with_capacity(4);
+        v.extend_from_slice(&[0x41414141u32; 4]);
+        assert_eq!(v.pop(), Some(0x41414141));
+        assert_eq!(v.len(), 3);
+        assert_eq!(spare(&v), [0]);
+    }
+
+    #[test]
+    fn truncate() {
+        let mut v = ClearingVec::with_capacity(4);
+        v.extend_from_slice(&[0x41414141u32; 4]);
+        v.truncate(1);
+        assert_eq!(&v[..], [0x41414141]);
+        assert_eq!(spare(&v), [0; 3]);
+
+        v.clear();
+        assert!(v.is_empty());
+        assert_eq!(spare(&v), [0; 4]);
+    }
+}
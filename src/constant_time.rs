@@ -0,0 +1,127 @@
+//! Comparisons which take the same time whatever the compared values.
+//!
+//! The usual `==` on slices and arrays stops at the first difference,
+//! so the time it takes tells how many leading bytes matched. When one
+//! of the compared values is a secret, for instance when checking a MAC,
+//! that can be enough to guess it one byte at a time.
+//!
+//! The `ConstantTimeEq` trait compares all of both values, accumulating
+//! the differences, and hides the intermediate results from the
+//! optimizer so that it cannot turn the comparison back into one which
+//! stops early.
+//!
+//! # Example
+//!
+//! ```
+//! # use clear_on_drop::constant_time::ConstantTimeEq;
+//! let expected = [0x41u8; 32];
+//! let received = [0x41u8; 32];
+//! assert!(expected.ct_eq(&received));
+//! ```
+
+use core::ops::DerefMut;
+
+use crate::clear::Clear;
+use crate::hide::hide_ptr;
+use crate::ClearOnDrop;
+
+/// Equality which takes the same time whatever the compared values.
+///
+/// The time can still depend on the lengths of the compared values,
+/// which are assumed not to be secret; slices of different lengths
+/// compare as different without looking at their contents.
+pub trait ConstantTimeEq {
+    /// Returns true if `self` and `other` are equal, taking the same
+    /// time whether they are equal or not, and wherever they differ.
+    fn ct_eq(&self, other: &Self) -> bool;
+}
+
+macro_rules! int_impl_constant_time_eq {
+    ($($T:ty => $U:ty),+) => {
+        $(
+            impl ConstantTimeEq for $T {
+                #[inline]
+                fn ct_eq(&self, other: &Self) -> bool {
+                    hide_ptr((*self as $U) ^ (*other as $U)) == 0
+                }
+            }
+        )+
+    }
+}
+
+int_impl_constant_time_eq! {
+    isize => usize, usize => usize,
+    i8 => u8, u8 => u8,
+    i16 => u16, u16 => u16,
+    i32 => u32, u32 => u32,
+    i64 => u64, u64 => u64,
+    i128 => u128, u128 => u128
+}
+
+impl<T: ConstantTimeEq> ConstantTimeEq for [T] {
+    fn ct_eq(&self, other: &Self) -> bool {
+        if self.len() != other.len() {
+            return false;
+        }
+        let mut equal = 1u8;
+        for (a, b) in self.iter().zip(other) {
+            // Hidden so that the loop cannot stop once it's zero.
+            equal = hide_ptr(equal & a.ct_eq(b) as u8);
+        }
+        equal == 1
+    }
+}
+
+impl<T: ConstantTimeEq, const N: usize> ConstantTimeEq for [T; N] {
+    #[inline]
+    fn ct_eq(&self, other: &Self) -> bool {
+        self[..].ct_eq(&other[..])
+    }
+}
+
+impl<P> ConstantTimeEq for ClearOnDrop<P>
+where
+    P: DerefMut,
+    P::Target: Clear + ConstantTimeEq,
+{
+    #[inline]
+    fn ct_eq(&self, other: &Self) -> bool {
+        (**self).ct_eq(&**other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConstantTimeEq;
+    use crate::ClearOnDrop;
+
+    #[test]
+    fn integers() {
+        assert!(0x41u8.ct_eq(&0x41));
+        assert!(!0x41u8.ct_eq(&0x42));
+        assert!((-1i32).ct_eq(&-1));
+        assert!(!(-1i64).ct_eq(&1));
+        assert!(!u128::MAX.ct_eq(&(u128::MAX >> 1)));
+    }
+
+    #[test]
+    fn slices() {
+        let a = [1u8, 2, 3, 4];
+        assert!(a.ct_eq(&[1, 2, 3, 4]));
+        assert!(!a.ct_eq(&[1, 2, 3, 5]));
+        assert!(!a.ct_eq(&[0, 2, 3, 4]));
+        assert!(!a[..].ct_eq(&a[..3]));
+        assert!([0u32; 0].ct_eq(&[]));
+    }
+
+    #[test]
+    fn clear_on_drop() {
+        let mut a = [0x41u8; 16];
+        let mut b = [0x41u8; 16];
+        let a = ClearOnDrop::new(&mut a);
+        let mut b = ClearOnDrop::new(&mut b);
+        assert!(a.ct_eq(&b));
+        b[15] = 0;
+        assert!(!a.ct_eq(&b));
+    }
+}
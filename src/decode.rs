@@ -0,0 +1,335 @@
+//! Decoding of hex and base64 text directly into cleared buffers.
+//!
+//! Key material is often given as text, for instance in an environment
+//! variable or the body of a PEM file. Decoding it with the usual
+//! functions leaves copies of the key in temporary buffers, which are
+//! freed without being cleared. The functions in this module write the
+//! decoded bytes directly into a buffer given by the caller, usually
+//! the contents of a `ClearOnDrop`, or with the `alloc` feature, into a
+//! new `ClearOnDrop<Box<[u8]>>` of the exact size.
+//!
+//! On error, the whole output buffer is cleared, so that no partially
+//! decoded key is left behind, and so is the small scratch buffer used
+//! while decoding base64.
+//!
+//! Each character is decoded with arithmetic instead of table lookups
+//! or comparisons, so the time taken does not depend on the value of
+//! the characters. Invalid characters are detected only after the whole
+//! input has been decoded, and the error does not say which character
+//! was invalid, nor where. Whitespace in base64 input is skipped, and
+//! the skipping does depend on where the whitespace is.
+//!
+//! # Example
+//!
+//! ```
+//! # use clear_on_drop::ClearOnDrop;
+//! # use clear_on_drop::decode::decode_hex_into;
+//! let mut key = [0u8; 4];
+//! let mut key = ClearOnDrop::new(&mut key);
+//! let len = decode_hex_into(b"deadbeef", &mut key[..]).unwrap();
+//! assert_eq!(len, 4);
+//! assert_eq!(*key, [0xde, 0xad, 0xbe, 0xef]);
+//! ```
+
+use core::fmt;
+
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use alloc::vec;
+
+use crate::clear::Clear;
+use crate::ClearOnDrop;
+
+/// An error while decoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input has a character which is not valid in the encoding.
+    InvalidCharacter,
+    /// The input has a number of characters which cannot be decoded,
+    /// misplaced padding, or unused bits which are not zero.
+    InvalidLength,
+    /// The output buffer is too small for the decoded bytes.
+    OutputTooSmall,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            DecodeError::InvalidCharacter => "invalid character",
+            DecodeError::InvalidLength => "invalid length",
+            DecodeError::OutputTooSmall => "output buffer too small",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {}
+
+/// Returns -1 if `c` is between `lo` and `hi` inclusive, or 0 if not,
+/// without branches.
+#[inline(always)]
+fn in_range(c: i16, lo: u8, hi: u8) -> i16 {
+    ((lo as i16 - 1 - c) & (c - hi as i16 - 1)) >> 8
+}
+
+/// Returns the value of a hex digit, or -1 if it's not one.
+#[inline(always)]
+fn hex_value(c: u8) -> i16 {
+    let c = c as i16;
+    let digit = in_range(c, b'0', b'9') & (c - b'0' as i16 + 1);
+    let upper = in_range(c, b'A', b'F') & (c - b'A' as i16 + 11);
+    let lower = in_range(c, b'a', b'f') & (c - b'a' as i16 + 11);
+    (digit | upper | lower) - 1
+}
+
+/// Returns the value of a character of the standard base64 alphabet,
+/// or -1 if it's not one.
+#[inline(always)]
+fn base64_value(c: u8) -> i16 {
+    let c = c as i16;
+    let upper = in_range(c, b'A', b'Z') & (c - b'A' as i16 + 1);
+    let lower = in_range(c, b'a', b'z') & (c - b'a' as i16 + 27);
+    let digit = in_range(c, b'0', b'9') & (c - b'0' as i16 + 53);
+    let plus = in_range(c, b'+', b'+') & 63;
+    let slash = in_range(c, b'/', b'/') & 64;
+    (upper | lower | digit | plus | slash) - 1
+}
+
+#[inline]
+fn is_whitespace(c: u8) -> bool {
+    matches!(c, b' ' | b'\t' | b'\r' | b'\n')
+}
+
+/// Returns the number of bytes encoded by a hex string.
+#[inline]
+pub fn hex_decoded_len(input: &[u8]) -> usize {
+    input.len() / 2
+}
+
+/// Returns the number of bytes encoded by a base64 string, ignoring
+/// whitespace and padding.
+pub fn base64_decoded_len(input: &[u8]) -> usize {
+    let chars = input
+        .iter()
+        .filter(|&&c| !is_whitespace(c) && c != b'=')
+        .count();
+    chars / 4 * 3 + (chars % 4) * 3 / 4
+}
+
+/// Decodes a hex string into `output`, returning the number of bytes
+/// written.
+///
+/// Both uppercase and lowercase digits are accepted. On error, all of
+/// `output` is cleared.
+pub fn decode_hex_into(input: &[u8], output: &mut [u8]) -> Result<usize, DecodeError> {
+    let result = hex_into(input, output);
+    if result.is_err() {
+        output.clear();
+    }
+    result
+}
+
+fn hex_into(input: &[u8], output: &mut [u8]) -> Result<usize, DecodeError> {
+    if input.len() % 2 != 0 {
+        return Err(DecodeError::InvalidLength);
+    }
+    let len = hex_decoded_len(input);
+    if len > output.len() {
+        return Err(DecodeError::OutputTooSmall);
+    }
+    let mut invalid = 0;
+    for (pair, out) in input.chunks_exact(2).zip(&mut output[..len]) {
+        let hi = hex_value(pair[0]);
+        let lo = hex_value(pair[1]);
+        invalid |= hi | lo;
+        *out = (hi << 4 | lo) as u8;
+    }
+    if invalid < 0 {
+        return Err(DecodeError::InvalidCharacter);
+    }
+    Ok(len)
+}
+
+/// Decodes a base64 string into `output`, returning the number of bytes
+/// written.
+///
+/// The standard alphabet is used, with optional padding. Whitespace is
+/// skipped, so that the body of a PEM file can be decoded directly. On
+/// error, all of `output` is cleared.
+pub fn decode_base64_into(input: &[u8], output: &mut [u8]) -> Result<usize, DecodeError> {
+    let result = base64_into(input, output);
+    if result.is_err() {
+        output.clear();
+    }
+    result
+}
+
+fn base64_into(input: &[u8], output: &mut [u8]) -> Result<usize, DecodeError> {
+    if base64_decoded_len(input) > output.len() {
+        return Err(DecodeError::OutputTooSmall);
+    }
+    // Holds up to four decoded characters, three bytes in total.
+    let mut scratch = 0u32;
+    let mut scratch = ClearOnDrop::new(&mut scratch);
+    let mut invalid = 0;
+    let mut chars = 0;
+    let mut padding = 0;
+    let mut len = 0;
+    for &c in input {
+        if is_whitespace(c) {
+            continue;
+        }
+        if c == b'=' {
+            padding += 1;
+            continue;
+        }
+        if padding != 0 {
+            return Err(DecodeError::InvalidLength);
+        }
+        let value = base64_value(c);
+        invalid |= value;
+        *scratch = *scratch << 6 | (value & 0x3f) as u32;
+        chars += 1;
+        if chars == 4 {
+            // Written directly, without an array of bytes which could be
+            // left on the stack.
+            output[len] = (*scratch >> 16) as u8;
+            output[len + 1] = (*scratch >> 8) as u8;
+            output[len + 2] = *scratch as u8;
+            len += 3;
+            chars = 0;
+        }
+    }
+    if padding != 0 && (chars + padding != 4 || padding > 2) {
+        return Err(DecodeError::InvalidLength);
+    }
+    // The unused low bits of the last character must be zero.
+    let extra = match chars {
+        0 => 0,
+        2 => {
+            output[len] = (*scratch >> 4) as u8;
+            *scratch & 0xf
+        }
+        3 => {
+            output[len] = (*scratch >> 10) as u8;
+            output[len + 1] = (*scratch >> 2) as u8;
+            *scratch & 0x3
+        }
+        _ => return Err(DecodeError::InvalidLength),
+    };
+    len += chars * 3 / 4;
+    if invalid < 0 {
+        return Err(DecodeError::InvalidCharacter);
+    }
+    if extra != 0 {
+        return Err(DecodeError::InvalidLength);
+    }
+    Ok(len)
+}
+
+/// Decodes a hex string into a new cleared buffer of the exact size.
+#[cfg(feature = "alloc")]
+pub fn decode_hex(input: &[u8]) -> Result<ClearOnDrop<Box<[u8]>>, DecodeError> {
+    let mut place = ClearOnDrop::new(vec![0; hex_decoded_len(input)].into_boxed_slice());
+    decode_hex_into(input, &mut place)?;
+    Ok(place)
+}
+
+/// Decodes a base64 string into a new cleared buffer of the exact size.
+///
+/// See `decode_base64_into` for the accepted input.
+#[cfg(feature = "alloc")]
+pub fn decode_base64(input: &[u8]) -> Result<ClearOnDrop<Box<[u8]>>, DecodeError> {
+    let mut place = ClearOnDrop::new(vec![0; base64_decoded_len(input)].into_boxed_slice());
+    decode_base64_into(input, &mut place)?;
+    Ok(place)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex() {
+        let mut buf = [0u8; 4];
+        assert_eq!(decode_hex_into(b"0aF9", &mut buf), Ok(2));
+        assert_eq!(buf[..2], [0x0a, 0xf9]);
+        assert_eq!(decode_hex_into(b"", &mut buf), Ok(0));
+        for c in 0..=255u8 {
+            let expected = (c as char).to_digit(16).map(|d| d as i16).unwrap_or(-1);
+            assert_eq!(hex_value(c), expected, "{}", c);
+        }
+    }
+
+    #[test]
+    fn hex_errors() {
+        let mut buf = [0x41u8; 2];
+        assert_eq!(
+            decode_hex_into(b"abcg", &mut buf),
+            Err(DecodeError::InvalidCharacter)
+        );
+        assert_eq!(buf, [0; 2]);
+        assert_eq!(
+            decode_hex_into(b"abc", &mut buf),
+            Err(DecodeError::InvalidLength)
+        );
+        assert_eq!(
+            decode_hex_into(b"abcdef", &mut buf),
+            Err(DecodeError::OutputTooSmall)
+        );
+    }
+
+    #[test]
+    fn base64() {
+        let cases: [(&[u8], &[u8]); 7] = [
+            (b"", b""),
+            (b"Zg==", b"f"),
+            (b"Zm8=", b"fo"),
+            (b"Zm9v", b"foo"),
+            (b"Zm9vYg", b"foob"),
+            (b"Zm9v\r\nYmE=\n", b"fooba"),
+            (b"+/+/", &[0xfb, 0xff, 0xbf]),
+        ];
+        for &(input, expected) in &cases {
+            let mut buf = [0u8; 8];
+            let len = decode_base64_into(input, &mut buf).unwrap();
+            assert_eq!(&buf[..len], expected);
+            assert_eq!(base64_decoded_len(input), len);
+        }
+        let alphabet = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        for c in 0..=255u8 {
+            let expected = alphabet.iter().position(|&a| a == c);
+            assert_eq!(base64_value(c), expected.map(|v| v as i16).unwrap_or(-1));
+        }
+    }
+
+    #[test]
+    fn base64_errors() {
+        let mut buf = [0x41u8; 3];
+        assert_eq!(
+            decode_base64_into(b"Zm9*", &mut buf),
+            Err(DecodeError::InvalidCharacter)
+        );
+        assert_eq!(buf, [0; 3]);
+        for &input in &[&b"Z"[..], b"Zg=", b"Zg===", b"Zg==Zg==", b"Zh=="] {
+            assert_eq!(
+                decode_base64_into(input, &mut buf),
+                Err(DecodeError::InvalidLength)
+            );
+        }
+        assert_eq!(
+            decode_base64_into(b"Zm9vYg==", &mut buf),
+            Err(DecodeError::OutputTooSmall)
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn boxed() {
+        assert_eq!(&decode_hex(b"00ff").unwrap()[..], [0x00, 0xff]);
+        assert_eq!(&decode_base64(b"Zm9vYmE=").unwrap()[..], b"fooba");
+        assert!(decode_base64(b"Zm9vYmE").is_ok());
+        assert_eq!(decode_hex(b"0g").err(), Some(DecodeError::InvalidCharacter));
+    }
+}
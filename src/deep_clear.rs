@@ -0,0 +1,365 @@
+#[cfg(feature = "alloc")]
+use alloc::collections::{BTreeMap, VecDeque};
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+
+use crate::clear::ZeroSafe;
+#[cfg(feature = "alloc")]
+use crate::clearing_vec::clear_spare;
+use crate::hide::hide_mem_impl;
+
+/// An operation to overwrite a value, including the memory it owns on
+/// the heap, before it's freed.
+///
+/// `Clear` overwrites only the memory of the value itself: clearing a
+/// `Vec<u8>` drops it, which frees its buffer without overwriting it,
+/// and then zeroes the pointer, capacity and length. `DeepClear` also
+/// overwrites what the value points to, so a `Vec` has its elements
+/// deep-cleared and its whole buffer (including the spare capacity)
+/// overwritten with zeros, and is left empty, keeping its capacity.
+///
+/// It's implemented for `ZeroSafe` types, which are set to zero, and
+/// for the standard collections and strings. Types which own other
+/// memory can implement it by deep-clearing each of their fields.
+///
+/// # Maps
+///
+/// A `HashMap` or `BTreeMap` is only partly overwritten. Its values are
+/// deep-cleared in place, but its keys can't be changed in place, since
+/// that would break the map. The keys are instead moved out of the map
+/// (which is left empty) and deep-cleared, which overwrites the memory
+/// they own (for instance, the buffer of a `String` key), but not the
+/// key itself: its copy in the memory of the map, like the hashes stored
+/// by a `HashMap`, is left there. A `BTreeMap` frees that memory without
+/// overwriting it, and a `HashMap` keeps it as its capacity. To have it
+/// overwritten when it's freed, use a `ClearingAllocator` as the global
+/// allocator.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "alloc")]
+/// # {
+/// # use clear_on_drop::DeepClear;
+/// let mut keys = vec![String::from("secret"), String::from("key")];
+/// keys.deep_clear();
+/// assert!(keys.is_empty());
+/// # }
+/// ```
+pub trait DeepClear {
+    /// Overwrites this value, and the memory it owns, leaving it empty
+    /// or zeroed.
+    fn deep_clear(&mut self);
+
+    /// Overwrites each value of a slice, and the memory they own.
+    ///
+    /// The default implementation calls `deep_clear` for each element;
+    /// `ZeroSafe` types set the whole slice to zero at once.
+    #[inline]
+    fn deep_clear_slice(slice: &mut [Self])
+    where
+        Self: Sized,
+    {
+        for x in slice {
+            x.deep_clear();
+        }
+    }
+}
+
+/// Sets a place to zero, in a way which won't be elided.
+#[inline]
+unsafe fn zero<T: ?Sized>(place: *mut T) {
+    ptr::write_bytes(place as *mut u8, 0, mem::size_of_val(&*place));
+    hide_mem_impl::<T>(place);
+}
+
+impl<T: ZeroSafe> DeepClear for T {
+    #[inline]
+    fn deep_clear(&mut self) {
+        unsafe { zero(self as *mut Self) }
+    }
+
+    #[inline]
+    fn deep_clear_slice(slice: &mut [Self]) {
+        unsafe { zero(slice as *mut [Self]) }
+    }
+}
+
+impl<T: DeepClear> DeepClear for [T] {
+    #[inline]
+    fn deep_clear(&mut self) {
+        T::deep_clear_slice(self)
+    }
+}
+
+impl DeepClear for str {
+    #[inline]
+    fn deep_clear(&mut self) {
+        // All zeros is valid UTF-8.
+        unsafe { zero(self as *mut str) }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: DeepClear> DeepClear for Vec<T> {
+    fn deep_clear(&mut self) {
+        T::deep_clear_slice(self);
+        self.clear();
+        clear_spare(self, 0);
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl DeepClear for String {
+    fn deep_clear(&mut self) {
+        // All zeros is valid UTF-8.
+        unsafe { self.as_mut_vec().deep_clear() }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: DeepClear> DeepClear for VecDeque<T> {
+    fn deep_clear(&mut self) {
+        let (a, b) = self.as_mut_slices();
+        T::deep_clear_slice(a);
+        T::deep_clear_slice(b);
+        self.clear();
+        // Converting an empty VecDeque to a Vec and back neither moves
+        // nor reallocates its buffer.
+        let mut vec = Vec::from(mem::take(self));
+        clear_spare(&mut vec, 0);
+        *self = VecDeque::from(vec);
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<K: DeepClear, V: DeepClear> DeepClear for BTreeMap<K, V> {
+    fn deep_clear(&mut self) {
+        for value in self.values_mut() {
+            value.deep_clear();
+        }
+        for (mut key, _) in mem::take(self) {
+            key.deep_clear();
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K: DeepClear, V: DeepClear, S> DeepClear for HashMap<K, V, S> {
+    fn deep_clear(&mut self) {
+        for value in self.values_mut() {
+            value.deep_clear();
+        }
+        for (mut key, _) in self.drain() {
+            key.deep_clear();
+        }
+    }
+}
+
+/// Wrapper which deep-clears a value when dropped.
+///
+/// This is the equivalent of `ClearOnDrop` for values which own memory
+/// on the heap, like collections: the value is held directly (not
+/// through a pointer), and when the wrapper is dropped, the value is
+/// deep-cleared with `DeepClear` before it's dropped.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "alloc")]
+/// # {
+/// # use clear_on_drop::DeepClearOnDrop;
+/// let mut keys = DeepClearOnDrop::new(Vec::new());
+/// keys.push(vec![0x41u8; 32]);
+/// # }
+/// ```
+#[derive(Default)]
+pub struct DeepClearOnDrop<T: DeepClear> {
+    value: T,
+}
+
+impl<T: DeepClear> DeepClearOnDrop<T> {
+    /// Wraps a value, to be deep-cleared when dropped.
+    #[inline]
+    pub fn new(value: T) -> Self {
+        DeepClearOnDrop { value }
+    }
+
+    /// Returns the wrapped value, without clearing it.
+    #[inline]
+    pub fn into_uncleared(c: Self) -> T {
+        let c = mem::ManuallyDrop::new(c);
+        unsafe { ptr::read(&c.value) }
+    }
+}
+
+impl<T: DeepClear> Deref for DeepClearOnDrop<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: DeepClear> DerefMut for DeepClearOnDrop<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T: DeepClear + fmt::Debug> fmt::Debug for DeepClearOnDrop<T> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.value, f)
+    }
+}
+
+impl<T: DeepClear> Drop for DeepClearOnDrop<T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.value.deep_clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeepClear, DeepClearOnDrop};
+
+    #[test]
+    fn zero_safe() {
+        let mut place = [0x41u32; 8];
+        place.deep_clear();
+        assert_eq!(place, [0; 8]);
+        let mut place = (1u8, 2.5f64);
+        place.deep_clear();
+        assert_eq!(place, (0, 0.0));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn vec() {
+        use std::slice;
+
+        let mut place = vec![0x41u8; 16];
+        place.reserve(16);
+        place.deep_clear();
+        assert!(place.is_empty());
+        let buf = unsafe { slice::from_raw_parts(place.as_ptr(), place.capacity()) };
+        assert!(buf.iter().all(|&b| b == 0));
+
+        let mut place = vec![String::from("secret"); 4];
+        place[..].deep_clear();
+        for s in &place {
+            let buf = unsafe { slice::from_raw_parts(s.as_ptr(), s.capacity()) };
+            assert!(s.is_empty() && buf.iter().all(|&b| b == 0));
+        }
+        place.deep_clear();
+        assert!(place.is_empty());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn string() {
+        use std::slice;
+
+        let mut place = String::from("correct horse battery staple");
+        place.deep_clear();
+        assert!(place.is_empty());
+        let buf = unsafe { slice::from_raw_parts(place.as_ptr(), place.capacity()) };
+        assert!(buf.iter().all(|&b| b == 0));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn vec_deque() {
+        use std::collections::VecDeque;
+        use std::slice;
+
+        let mut place: VecDeque<u32> = VecDeque::with_capacity(4);
+        place.extend([0x41414141; 4]);
+        place.pop_front();
+        place.pop_front();
+        // Wraps around the end of the buffer.
+        place.extend([0x42424242; 2]);
+        let capacity = place.capacity();
+        let head = place.as_slices().0.as_ptr();
+        place.deep_clear();
+        assert!(place.is_empty());
+        assert_eq!(place.capacity(), capacity);
+
+        // An empty VecDeque keeps its buffer when converted to a Vec.
+        let place = Vec::from(place);
+        let buf = unsafe { slice::from_raw_parts(place.as_ptr(), place.capacity()) };
+        assert!(buf.as_ptr_range().contains(&head));
+        assert!(buf.iter().all(|&x| x == 0));
+    }
+
+    /// A key or value which records its id when it's deep-cleared.
+    #[cfg(feature = "alloc")]
+    #[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+    struct Probe(u32);
+
+    #[cfg(feature = "alloc")]
+    std::thread_local! {
+        static CLEARED: std::cell::RefCell<Vec<u32>> = Default::default();
+    }
+
+    #[cfg(feature = "alloc")]
+    impl DeepClear for Probe {
+        fn deep_clear(&mut self) {
+            CLEARED.with(|cleared| cleared.borrow_mut().push(self.0));
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    fn take_cleared() -> Vec<u32> {
+        let mut cleared = CLEARED.with(|cleared| cleared.take());
+        cleared.sort_unstable();
+        cleared
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn btree_map() {
+        use std::collections::BTreeMap;
+
+        let mut place = BTreeMap::new();
+        place.insert(Probe(1), Probe(10));
+        place.insert(Probe(2), Probe(20));
+        place.deep_clear();
+        assert!(place.is_empty());
+        assert_eq!(take_cleared(), [1, 2, 10, 20]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn hash_map() {
+        use std::collections::HashMap;
+
+        let mut place = HashMap::new();
+        place.insert(Probe(1), Probe(10));
+        place.insert(Probe(2), Probe(20));
+        place.deep_clear();
+        assert!(place.is_empty());
+        assert_eq!(take_cleared(), [1, 2, 10, 20]);
+    }
+
+    #[test]
+    fn wrapper() {
+        let mut place = DeepClearOnDrop::new([0x41u8; 4]);
+        place[0] = 0;
+        assert_eq!(*place, [0, 0x41, 0x41, 0x41]);
+        let value = DeepClearOnDrop::into_uncleared(place);
+        assert_eq!(value, [0, 0x41, 0x41, 0x41]);
+    }
+}
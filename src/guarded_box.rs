@@ -0,0 +1,213 @@
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::fs::File;
+use std::io::{self, Read};
+use std::process;
+
+use crate::pages::{page_size, Pages};
+
+/// A pointer type for heap allocation in locked memory, surrounded by
+/// guard pages.
+///
+/// Like `SecureBox`, the value is stored in locked memory which is
+/// overwritten with zeros when freed. Additionally, the allocation is
+/// surrounded by inaccessible guard pages, so that a buffer overflow
+/// causes a fault instead of reading or writing adjacent data.
+///
+/// The value is placed at the end of its pages, so that overflows past
+/// its end immediately reach the guard page. Underflows are detected
+/// through a canary word placed just before the value, which is checked
+/// when the `GuardedBox` is dropped; if it has been modified, the
+/// process is aborted.
+///
+/// Each `GuardedBox` uses at least three pages of address space, one
+/// of them locked, so it should be used only for small amounts of
+/// sensitive data.
+///
+/// # Example
+///
+/// ```
+/// # use clear_on_drop::{ClearOnDrop, GuardedBox};
+/// let place: GuardedBox<[u8; 32]> = GuardedBox::default();
+/// let mut key = ClearOnDrop::new(place);
+/// key.copy_from_slice(&[0x41; 32]);
+/// ```
+pub struct GuardedBox<T> {
+    pages: Pages,
+    offset: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T> GuardedBox<T> {
+    /// Allocates guarded locked memory and places `value` into it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the memory could not be allocated or locked.
+    #[inline]
+    pub fn new(value: T) -> Self {
+        match Self::try_new(value) {
+            Ok(b) => b,
+            Err(err) => panic!("failed to allocate locked memory: {}", err),
+        }
+    }
+
+    /// Allocates guarded locked memory and places `value` into it,
+    /// returning an error if the memory could not be allocated or
+    /// locked.
+    pub fn try_new(value: T) -> io::Result<Self> {
+        let align = mem::align_of::<T>().max(mem::align_of::<usize>());
+        assert!(align <= page_size());
+        let canary = canary()?;
+        let size = mem::size_of::<T>();
+        let pages = Pages::with_guard_pages(size + mem::size_of::<usize>() + align)?;
+        let offset = (pages.len() - size) & !(align - 1);
+        unsafe {
+            let ptr = pages.as_ptr().add(offset);
+            ptr::write((ptr as *mut usize).sub(1), canary);
+            ptr::write(ptr as *mut T, value);
+        }
+        Ok(GuardedBox {
+            pages,
+            offset,
+            _marker: PhantomData,
+        })
+    }
+
+    #[inline]
+    fn as_ptr(&self) -> *mut T {
+        unsafe { self.pages.as_ptr().add(self.offset) as *mut T }
+    }
+
+    #[inline]
+    fn check_canary(&self) {
+        let expected = CANARY.load(Ordering::Relaxed);
+        let found = unsafe { ptr::read_volatile((self.as_ptr() as *const usize).sub(1)) };
+        if found != expected {
+            // The memory is corrupted; do not run any more code which
+            // could make use of it.
+            process::abort();
+        }
+    }
+}
+
+static CANARY: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the canary value, generating it on the first call.
+fn canary() -> io::Result<usize> {
+    let canary = CANARY.load(Ordering::Relaxed);
+    if canary != 0 {
+        return Ok(canary);
+    }
+
+    let mut bytes = [0u8; mem::size_of::<usize>()];
+    File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    // Never zero, which marks the canary as not yet generated.
+    let new = usize::from_ne_bytes(bytes) | 1;
+    match CANARY.compare_exchange(0, new, Ordering::Relaxed, Ordering::Relaxed) {
+        Ok(_) => Ok(new),
+        Err(current) => Ok(current),
+    }
+}
+
+impl<T: Default> Default for GuardedBox<T> {
+    #[inline]
+    fn default() -> Self {
+        GuardedBox::new(Default::default())
+    }
+}
+
+impl<T> Deref for GuardedBox<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        unsafe { &*self.as_ptr() }
+    }
+}
+
+impl<T> DerefMut for GuardedBox<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.as_ptr() }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for GuardedBox<T> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T> Drop for GuardedBox<T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.check_canary();
+        // The pages are cleared when dropped, after this.
+        unsafe {
+            ptr::drop_in_place(self.as_ptr());
+        }
+    }
+}
+
+unsafe impl<T: Send> Send for GuardedBox<T> {}
+unsafe impl<T: Sync> Sync for GuardedBox<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::GuardedBox;
+    use crate::ClearOnDrop;
+    use std::mem;
+    use std::rc::Rc;
+
+    #[derive(Debug, Default)]
+    struct Place {
+        data: [u32; 4],
+    }
+
+    const DATA: [u32; 4] = [0x01234567, 0x89abcdef, 0xfedcba98, 0x76543210];
+
+    #[test]
+    fn new() {
+        let place = GuardedBox::new(Place { data: DATA });
+        assert_eq!(place.data, DATA);
+    }
+
+    #[test]
+    fn at_end_of_page() {
+        let place = GuardedBox::new(Place { data: DATA });
+        let end = &*place as *const Place as usize + mem::size_of::<Place>();
+        assert_eq!(end % crate::pages::page_size(), 0);
+    }
+
+    #[test]
+    fn is_aligned() {
+        let place = GuardedBox::new([0u64; 3]);
+        assert_eq!(&*place as *const _ as usize % mem::align_of::<u64>(), 0);
+    }
+
+    #[test]
+    fn drops_value() {
+        let rc = Rc::new(());
+        let place = GuardedBox::new(Rc::clone(&rc));
+        assert_eq!(Rc::strong_count(&rc), 2);
+        drop(place);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn on_clear_on_drop() {
+        let place: GuardedBox<Place> = GuardedBox::default();
+        let mut clear = ClearOnDrop::new(place);
+        clear.data = DATA;
+        assert_eq!(clear.data, DATA);
+
+        let place = ClearOnDrop::into_place(clear);
+        assert_eq!(place.data, [0; 4]);
+    }
+}
@@ -24,8 +24,11 @@ pub fn hide_ptr<P>(mut ptr: P) -> P {
 
 pub use self::impls::hide_mem_impl;
 
+// Which implementation is used is decided by the build script, from the
+// features and the target architecture.
+
 // On nightly, inline assembly can be used.
-#[cfg(feature = "nightly")]
+#[cfg(clear_on_drop_hide = "nightly")]
 mod impls {
     use core::arch::asm;
 
@@ -60,7 +63,7 @@ mod impls {
 }
 
 // When a C compiler is available, a dummy C function can be used.
-#[cfg(not(feature = "no_cc"))]
+#[cfg(clear_on_drop_hide = "cc")]
 mod impls {
     extern "C" {
         fn clear_on_drop_hide(ptr: *mut u8) -> *mut u8;
@@ -74,9 +77,24 @@ mod impls {
     }
 }
 
-// When neither is available, pretend the pointer is sent to a thread,
-// and hope this is enough to confuse the optimizer.
-#[cfg(all(feature = "no_cc", not(feature = "nightly")))]
+// On stable, inline assembly can be used on the architectures where it
+// has been stabilized; without specialization, the same code is used
+// for sized and unsized types.
+#[cfg(clear_on_drop_hide = "asm")]
+mod impls {
+    use core::arch::asm;
+
+    #[inline]
+    pub fn hide_mem_impl<T: ?Sized>(ptr: *mut T) {
+        unsafe {
+            asm!("/* {0} */", in(reg) (ptr as *mut u8), options(nostack));
+        }
+    }
+}
+
+// When none is available, pretend the pointer is sent to a thread, and
+// hope this is enough to confuse the optimizer.
+#[cfg(clear_on_drop_hide = "fallback")]
 mod impls {
     use core::sync::atomic::{AtomicUsize, Ordering};
 
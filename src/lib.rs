@@ -1,4 +1,4 @@
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 #![cfg_attr(feature = "nightly", feature(min_specialization))]
 #![deny(missing_docs)]
 
@@ -22,43 +22,183 @@
 //! dropped. While the mutable reference is held, the data cannot be
 //! moved, so there won't be leftovers due to moves; the wrapper itself
 //! can be freely moved. Alternatively, it can hold data on the heap
-//! (using a `Box<T>`, or possibly a similar which allocates from a
+//! (using a `Box<T>`, or a `SecureBox<T>` which allocates from a
 //! `mlock`ed heap).
 //!
+//! With the `alloc` feature, the `Secret` type holds data on the heap,
+//! clears it when dropped, and makes it harder to leak by accident: it
+//! cannot be cloned, its `Debug` output is redacted, and its contents
+//! are reached only through `expose_secret`. Comparing two `Secret`
+//! values with `==` uses the `ConstantTimeEq` trait, from the
+//! `constant_time` module, whose comparisons take the same time wherever
+//! the values differ.
+//!
 //! The `clear_stack_on_return` function calls a closure, and after it
 //! returns, overwrites several kilobytes of the stack. This can help
 //! overwrite temporary variables used by cryptographic algorithms, and
 //! is especially relevant when running on a short-lived thread, since
 //! the memory used for the thread stack cannot be easily overwritten
-//! after the thread terminates.
+//! after the thread terminates. The amount of stack to overwrite can be
+//! measured with `measure_stack_on_return`. Passing a
+//! `ClearStackOptions` with `registers` set also overwrites the scratch
+//! and vector registers.
+//!
+//! With the `std` feature on Unix systems, the `spawn_scrubbed` function
+//! runs a closure on a new thread whose stack is allocated from locked
+//! memory, and overwritten after the thread exits.
+//!
+//! On Linux, on x86-64 and AArch64, the `with_secret_stack` function
+//! instead runs a closure on a separately allocated stack, which is
+//! overwritten in full when it returns, so no guess of how much stack it
+//! uses is needed.
+//!
+//! With the `serde` feature, a `ClearOnDrop<Box<T>>` or a `Secret<T>`
+//! can be deserialized directly into its heap allocation, without
+//! leaving copies in intermediate buffers. Serializing them needs an
+//! explicit opt-in through the `SerializableSecret` trait.
+//!
+//! The `decode` module decodes hex and base64 text, such as keys from
+//! environment variables or PEM files, directly into a cleared buffer,
+//! and clears the partially decoded output on errors.
+//!
+//! With the `std` feature on Unix systems, the `read_secret_line`
+//! function reads a password or passphrase from the terminal, with echo
+//! turned off, into a buffer which never grows and is cleared when
+//! dropped.
+//!
+//! For `async` code, the `ClearOnDropFuture` wrapper polls a future with
+//! `clear_stack_on_return`, and clears the state of the future (which
+//! holds the local variables of an `async` block) when it completes or
+//! is dropped.
 //!
 //! # Preventing compiler optimizations
 //!
 //! If the compiler determines the data is not used after being cleared,
 //! it could elide the clearing code. Aditionally, the compiler could
 //! inline a called function and the stack clearing code, using separate
-//! areas of the stack for each. This crate has three mechanisms which
+//! areas of the stack for each. This crate has four mechanisms which
 //! prevent these unwanted optimizations, selected at compile time via
 //! cargo features.
 //!
-//! The fastest mechanism uses inline assembly, which is only available
-//! on nightly Rust. It is enabled through the `nightly` feature, and
-//! does not need a working C compiler.
+//! The default mechanism uses inline assembly, which is available on
+//! stable Rust for the x86, x86-64, ARM, AArch64, RISC-V and LoongArch
+//! architectures. It does not need a C compiler.
 //!
-//! The second mechanism, which is the default, uses a call to a dummy
-//! C function. It works on stable Rust, but needs a working C compiler.
+//! The `nightly` feature enables an older variant of the inline assembly
+//! mechanism, which uses specialization, and is only available on
+//! nightly Rust.
 //!
-//! The third mechanism is a fallback, which attempts to confuse the
+//! The `cc` feature enables a mechanism which uses a call to a dummy C
+//! function. It works on stable Rust on every architecture, but needs a
+//! working C compiler. The `no_cc` feature disables it again.
+//!
+//! The last mechanism is a fallback, which attempts to confuse the
 //! optimizer through the use of atomic instructions. It should not be
-//! used unless necessary, since it's less reliable. It is enabled by
-//! the `no_cc` feature, works on stable Rust, and does not need a C
-//! compiler.
+//! used unless necessary, since it's less reliable. It is used on other
+//! architectures when the `cc` feature is not enabled, and can be
+//! selected with the `no_asm` feature.
+//!
+//! The `verify` test (in `tests/verify.rs`, whose documentation explains
+//! how to run it) checks that the clearing is still done in optimized
+//! code, with the mechanism selected by the features. With link-time
+//! optimization, the fallback mechanism fails to keep the clearing of a
+//! `ClearOnDrop` on the stack, so when the fallback is selected, that
+//! check asserts the clearing is missing instead.
+//!
+//! # Secure heap
+//!
+//! The `std` feature enables the `SecureBox` container on Unix systems,
+//! which allocates its contents from locked memory, excluded from core
+//! dumps and cleared when freed. The `GuardedBox` container does the
+//! same, and also surrounds its contents with guard pages and a canary.
+//! The `ProtectedBox` container keeps its contents inaccessible, except
+//! during explicit calls to access them.
+//!
+//! The `ClearingAllocator` wrapper, which does not need the `std`
+//! feature, can be used as the global allocator to clear all memory
+//! when it is freed, including the buffers left behind when a `Vec` or
+//! `String` grows. Alternatively, the `alloc` feature enables the
+//! `ClearingVec` and `ClearingString` types, which clear their old
+//! buffers when they grow, without affecting other allocations.
+//!
+//! Clearing a collection with `ClearOnDrop` only overwrites the
+//! collection itself, not the memory it owns. The `DeepClear` trait, and
+//! the `DeepClearOnDrop` wrapper which uses it, also overwrite the
+//! elements and the whole buffer of a `Vec`, `VecDeque` or `String`, and
+//! the values of a `HashMap` or `BTreeMap` (whose keys can only be
+//! partly overwritten; see the documentation of `DeepClear`).
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
 
 pub mod clear;
+mod clear_and_flush_on_drop;
 mod clear_on_drop;
+mod clear_on_drop_future;
 mod clear_stack_on_return;
+mod clearing_allocator;
+#[cfg(feature = "alloc")]
+mod clearing_string;
+#[cfg(feature = "alloc")]
+mod clearing_vec;
+pub mod constant_time;
+pub mod decode;
+mod deep_clear;
 mod fnoption;
+#[cfg(all(feature = "std", unix))]
+mod guarded_box;
 mod hide;
+#[cfg(all(feature = "std", unix))]
+mod pages;
+#[cfg(all(feature = "std", unix))]
+mod protected_box;
+#[cfg(all(feature = "std", unix))]
+mod read_secret;
+#[cfg(all(feature = "std", unix))]
+mod scrubbed_thread;
+#[cfg(feature = "alloc")]
+mod secret;
+#[cfg(feature = "serde")]
+mod serde_impls;
+#[cfg(all(
+    feature = "std",
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod secret_stack;
+#[cfg(all(feature = "std", unix))]
+mod secure_box;
+mod stack_calibration;
+pub mod wipe;
 
+pub use crate::clear_and_flush_on_drop::*;
 pub use crate::clear_on_drop::*;
+pub use crate::clear_on_drop_future::*;
 pub use crate::clear_stack_on_return::*;
+pub use crate::clearing_allocator::*;
+#[cfg(feature = "alloc")]
+pub use crate::clearing_string::*;
+#[cfg(feature = "alloc")]
+pub use crate::clearing_vec::*;
+pub use crate::deep_clear::*;
+#[cfg(all(feature = "std", unix))]
+pub use crate::guarded_box::*;
+#[cfg(all(feature = "std", unix))]
+pub use crate::protected_box::*;
+#[cfg(all(feature = "std", unix))]
+pub use crate::read_secret::*;
+#[cfg(all(feature = "std", unix))]
+pub use crate::scrubbed_thread::*;
+pub use crate::stack_calibration::*;
+#[cfg(feature = "alloc")]
+pub use crate::secret::*;
+#[cfg(feature = "serde")]
+pub use crate::serde_impls::*;
+#[cfg(all(
+    feature = "std",
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+pub use crate::secret_stack::*;
+#[cfg(all(feature = "std", unix))]
+pub use crate::secure_box::*;
@@ -0,0 +1,221 @@
+//! Page-granular memory, allocated directly from the operating system.
+//!
+//! The memory is locked so that it will not be written to swap, and is
+//! excluded from core dumps where the operating system allows it. It is
+//! overwritten with zeros before being returned to the operating system.
+
+use core::mem;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::io;
+
+use crate::hide::hide_mem_impl;
+
+/// Returns the size of a memory page.
+pub fn page_size() -> usize {
+    static PAGE_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+    let mut size = PAGE_SIZE.load(Ordering::Relaxed);
+    if size == 0 {
+        size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        PAGE_SIZE.store(size, Ordering::Relaxed);
+    }
+    size
+}
+
+/// Rounds `len` up to a whole number of pages.
+pub fn round_to_pages(len: usize) -> usize {
+    let page_size = page_size();
+    len.checked_add(page_size - 1)
+        .expect("allocation too large")
+        & !(page_size - 1)
+}
+
+/// A locked region of memory, which is cleared when dropped.
+///
+/// The region can optionally be surrounded by inaccessible guard pages,
+/// so that overflows in either direction cause a fault instead of
+/// reaching other data.
+pub struct Pages {
+    ptr: NonNull<u8>,
+    len: usize,
+    guard: usize,
+}
+
+impl Pages {
+    /// Allocates and locks at least `len` bytes, rounded up to a whole
+    /// number of pages. The memory is initialized to zero.
+    #[inline]
+    pub fn new(len: usize) -> io::Result<Self> {
+        Self::map(len, 0)
+    }
+
+    /// Like `Pages::new`, but with a guard page on each side.
+    #[inline]
+    pub fn with_guard_pages(len: usize) -> io::Result<Self> {
+        Self::map(len, page_size())
+    }
+
+    fn map(len: usize, guard: usize) -> io::Result<Self> {
+        let len = round_to_pages(len.max(1));
+        let total = len.checked_add(2 * guard).expect("allocation too large");
+        unsafe {
+            let base = libc::mmap(
+                ptr::null_mut(),
+                total,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANON,
+                -1,
+                0,
+            );
+            if base == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            let ptr = (base as *mut u8).add(guard);
+            let pages = Pages {
+                ptr: NonNull::new_unchecked(ptr),
+                len,
+                guard,
+            };
+            if guard != 0 {
+                if let Err(err) = pages
+                    .protect_guard(base as *mut u8)
+                    .and_then(|()| pages.protect_guard(ptr.add(len)))
+                {
+                    return Err(pages.discard(err));
+                }
+            }
+            if libc::mlock(ptr as *const libc::c_void, len) != 0 {
+                return Err(pages.discard(io::Error::last_os_error()));
+            }
+            // Not all kernels support this; it's only a best effort.
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            libc::madvise(ptr as *mut libc::c_void, len, libc::MADV_DONTDUMP);
+            Ok(pages)
+        }
+    }
+
+    unsafe fn protect_guard(&self, guard: *mut u8) -> io::Result<()> {
+        if libc::mprotect(guard as *mut libc::c_void, self.guard, libc::PROT_NONE) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Unmaps the region without clearing it, after a failure while
+    /// setting it up, and returns `err`.
+    unsafe fn discard(self, err: io::Error) -> io::Error {
+        self.unmap();
+        // Already unmapped, so it must not be cleared and unmapped again.
+        mem::forget(self);
+        err
+    }
+
+    /// Returns a pointer to the start of the region.
+    #[inline]
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    /// Returns the length of the region, which is a whole number of
+    /// pages, not counting the guard pages.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Changes the access protection of the region, not counting the
+    /// guard pages, to `prot` (a combination of the `PROT_*` flags).
+    pub fn protect(&self, prot: libc::c_int) -> io::Result<()> {
+        let ret = unsafe { libc::mprotect(self.ptr.as_ptr() as *mut libc::c_void, self.len, prot) };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    unsafe fn unmap(&self) {
+        libc::munmap(
+            self.ptr.as_ptr().sub(self.guard) as *mut libc::c_void,
+            self.len + 2 * self.guard,
+        );
+    }
+}
+
+impl Drop for Pages {
+    fn drop(&mut self) {
+        unsafe {
+            // The region might have been left inaccessible.
+            libc::mprotect(
+                self.ptr.as_ptr() as *mut libc::c_void,
+                self.len,
+                libc::PROT_READ | libc::PROT_WRITE,
+            );
+            ptr::write_bytes(self.ptr.as_ptr(), 0, self.len);
+            hide_mem_impl::<[u8]>(ptr::slice_from_raw_parts_mut(self.ptr.as_ptr(), self.len));
+            libc::munlock(self.ptr.as_ptr() as *const libc::c_void, self.len);
+            self.unmap();
+        }
+    }
+}
+
+// The region is only a block of bytes; ownership of anything stored in
+// it is tracked by the containers using it.
+unsafe impl Send for Pages {}
+unsafe impl Sync for Pages {}
+
+/// Unwraps the result of something which allocates locked pages, or
+/// returns `None` if they couldn't be locked because of the limit on
+/// locked memory (which can be as low as 64 KiB), so the test using it
+/// can be skipped.
+#[cfg(test)]
+pub(crate) fn skip_if_unlockable<T>(result: io::Result<T>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(err) if matches!(err.raw_os_error(), Some(libc::EPERM) | Some(libc::ENOMEM)) => {
+            eprintln!("skipped, the memory can't be locked: {}", err);
+            None
+        }
+        Err(err) => panic!("{}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pages;
+
+    /// Runs `f` in a child process which can't lock any memory, and
+    /// returns its exit status.
+    fn without_mlock(f: fn() -> bool) -> libc::c_int {
+        unsafe {
+            let pid = libc::fork();
+            assert!(pid >= 0);
+            if pid == 0 {
+                // Root can lock memory beyond the limit.
+                if libc::getuid() == 0 && libc::setuid(65534) != 0 {
+                    libc::_exit(2);
+                }
+                let limit = libc::rlimit {
+                    rlim_cur: 0,
+                    rlim_max: 0,
+                };
+                if libc::setrlimit(libc::RLIMIT_MEMLOCK, &limit) != 0 {
+                    libc::_exit(2);
+                }
+                libc::_exit(if f() { 0 } else { 1 });
+            }
+            let mut status = 0;
+            assert_eq!(libc::waitpid(pid, &mut status, 0), pid);
+            status
+        }
+    }
+
+    #[test]
+    fn mlock_failure() {
+        assert_eq!(without_mlock(|| Pages::new(1 << 20).is_err()), 0);
+        assert_eq!(
+            without_mlock(|| Pages::with_guard_pages(1 << 20).is_err()),
+            0
+        );
+    }
+}
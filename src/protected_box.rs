@@ -0,0 +1,220 @@
+use core::cell::Cell;
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr;
+use std::io;
+
+use crate::clear::Clear;
+use crate::pages::{page_size, Pages};
+
+/// A heap container in locked memory, which is inaccessible except
+/// while being explicitly accessed.
+///
+/// Like `SecureBox`, the value is stored in locked memory which is
+/// overwritten with zeros when freed. Additionally, the memory is
+/// protected with `PROT_NONE` while at rest, so that any stray access
+/// to it causes a fault. It is made accessible only for the duration of
+/// a call to `with_read` or `with_write`, which keeps the window during
+/// which the value can be read short and explicit.
+///
+/// The value is cleared with `Clear::clear` when the `ProtectedBox` is
+/// dropped.
+///
+/// # Example
+///
+/// ```
+/// # use clear_on_drop::ProtectedBox;
+/// let mut key: ProtectedBox<[u8; 32]> = ProtectedBox::default();
+/// key.with_write(|key| key.copy_from_slice(&[0x41; 32]));
+/// let sum = key.with_read(|key| key.iter().map(|&b| b as u32).sum::<u32>());
+/// assert_eq!(sum, 0x41 * 32);
+/// ```
+pub struct ProtectedBox<T: Clear> {
+    pages: Pages,
+    readers: Cell<usize>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Clear> ProtectedBox<T> {
+    /// Allocates locked memory, places `value` into it, and makes it
+    /// inaccessible.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the memory could not be allocated or locked.
+    #[inline]
+    pub fn new(value: T) -> Self {
+        match Self::try_new(value) {
+            Ok(b) => b,
+            Err(err) => panic!("failed to allocate locked memory: {}", err),
+        }
+    }
+
+    /// Allocates locked memory, places `value` into it, and makes it
+    /// inaccessible, returning an error if the memory could not be
+    /// allocated, locked, or protected.
+    pub fn try_new(value: T) -> io::Result<Self> {
+        assert!(mem::align_of::<T>() <= page_size());
+        let pages = Pages::new(mem::size_of::<T>())?;
+        unsafe {
+            ptr::write(pages.as_ptr() as *mut T, value);
+        }
+        let b = ProtectedBox {
+            pages,
+            readers: Cell::new(0),
+            _marker: PhantomData,
+        };
+        b.pages.protect(libc::PROT_NONE)?;
+        Ok(b)
+    }
+
+    /// Calls `f` with a shared reference to the value, which is made
+    /// readable only for the duration of the call.
+    ///
+    /// Calls to `with_read` can be nested.
+    pub fn with_read<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        let _access = ReadAccess::new(self);
+        f(unsafe { &*(self.pages.as_ptr() as *const T) })
+    }
+
+    /// Calls `f` with a mutable reference to the value, which is made
+    /// readable and writable only for the duration of the call.
+    pub fn with_write<F, R>(&mut self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let _access = WriteAccess::new(self);
+        f(unsafe { &mut *(self.pages.as_ptr() as *mut T) })
+    }
+
+    #[inline]
+    fn protect(&self, prot: libc::c_int) {
+        if let Err(err) = self.pages.protect(prot) {
+            panic!("failed to change memory protection: {}", err);
+        }
+    }
+}
+
+impl<T: Clear + Default> Default for ProtectedBox<T> {
+    #[inline]
+    fn default() -> Self {
+        ProtectedBox::new(Default::default())
+    }
+}
+
+impl<T: Clear> Drop for ProtectedBox<T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.protect(libc::PROT_READ | libc::PROT_WRITE);
+        // The pages are cleared when dropped, after this.
+        unsafe {
+            let ptr = self.pages.as_ptr() as *mut T;
+            (*ptr).clear();
+            ptr::drop_in_place(ptr);
+        }
+    }
+}
+
+unsafe impl<T: Clear + Send> Send for ProtectedBox<T> {}
+
+/// Keeps the memory readable while alive, even during unwinding.
+struct ReadAccess<'a, T: Clear> {
+    b: &'a ProtectedBox<T>,
+}
+
+impl<'a, T: Clear> ReadAccess<'a, T> {
+    #[inline]
+    fn new(b: &'a ProtectedBox<T>) -> Self {
+        let readers = b.readers.get();
+        if readers == 0 {
+            b.protect(libc::PROT_READ);
+        }
+        b.readers.set(readers + 1);
+        ReadAccess { b }
+    }
+}
+
+impl<'a, T: Clear> Drop for ReadAccess<'a, T> {
+    #[inline]
+    fn drop(&mut self) {
+        let readers = self.b.readers.get() - 1;
+        self.b.readers.set(readers);
+        if readers == 0 {
+            self.b.protect(libc::PROT_NONE);
+        }
+    }
+}
+
+/// Keeps the memory writable while alive, even during unwinding.
+struct WriteAccess<'a, T: Clear> {
+    b: &'a ProtectedBox<T>,
+}
+
+impl<'a, T: Clear> WriteAccess<'a, T> {
+    #[inline]
+    fn new(b: &'a ProtectedBox<T>) -> Self {
+        b.protect(libc::PROT_READ | libc::PROT_WRITE);
+        WriteAccess { b }
+    }
+}
+
+impl<'a, T: Clear> Drop for WriteAccess<'a, T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.b.protect(libc::PROT_NONE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProtectedBox;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::rc::Rc;
+
+    #[derive(Debug, Default)]
+    struct Place {
+        data: [u32; 4],
+    }
+
+    const DATA: [u32; 4] = [0x01234567, 0x89abcdef, 0xfedcba98, 0x76543210];
+
+    #[test]
+    fn read_write() {
+        let mut place = ProtectedBox::new(Place::default());
+        place.with_write(|place| place.data = DATA);
+        assert_eq!(place.with_read(|place| place.data), DATA);
+    }
+
+    #[test]
+    fn nested_read() {
+        let place = ProtectedBox::new(Place { data: DATA });
+        place.with_read(|outer| {
+            assert_eq!(place.with_read(|inner| inner.data), DATA);
+            assert_eq!(outer.data, DATA);
+        });
+        assert_eq!(place.readers.get(), 0);
+    }
+
+    #[test]
+    fn read_after_panic() {
+        let place = ProtectedBox::new(Place { data: DATA });
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            place.with_read(|_| panic!("test"));
+        }));
+        assert!(result.is_err());
+        assert_eq!(place.readers.get(), 0);
+        assert_eq!(place.with_read(|place| place.data), DATA);
+    }
+
+    #[test]
+    fn drops_value() {
+        let rc = Rc::new(());
+        let place = ProtectedBox::new(Some(Rc::clone(&rc)));
+        assert_eq!(Rc::strong_count(&rc), 2);
+        drop(place);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+}
@@ -0,0 +1,157 @@
+use std::fs::OpenOptions;
+use std::io::{self, Read, Write};
+use std::mem::MaybeUninit;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use crate::{ClearOnDrop, ClearingString, ClearingVec};
+
+/// Reads a line from the terminal without echoing it, for instance a
+/// password or passphrase.
+///
+/// The `prompt` is written to the controlling terminal (`/dev/tty`),
+/// echo is turned off, and a line is read from the terminal, one byte
+/// at a time so that nothing after the line is buffered. The line is
+/// read into a buffer allocated up front with room for `capacity`
+/// bytes, which never grows, so there are no copies of the line left
+/// behind by reallocations. The terminal settings are restored before
+/// returning, even on errors.
+///
+/// The line is returned without its line terminator, in a
+/// `ClearingString` wrapped in a `ClearOnDrop`, which clears it when
+/// dropped. On every error, anything read so far is cleared.
+///
+/// Returns an error if there is no controlling terminal, if the line
+/// has more than `capacity` bytes (the rest of the line is then read
+/// and discarded), if it's not valid UTF-8, or if the end of the input
+/// is reached before anything is read.
+///
+/// Note: if the process is killed while waiting for the line, the
+/// terminal is left with echo turned off.
+///
+/// # Example
+///
+/// ```no_run
+/// # use clear_on_drop::read_secret_line;
+/// let passphrase = read_secret_line("Passphrase: ", 1024).unwrap();
+/// # let _ = passphrase.len();
+/// ```
+pub fn read_secret_line(prompt: &str, capacity: usize) -> io::Result<ClearOnDrop<ClearingString>> {
+    let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+    tty.write_all(prompt.as_bytes())?;
+    tty.flush()?;
+    let guard = EchoGuard::disable(tty.as_raw_fd())?;
+    let line = read_line(&mut &tty, capacity);
+    drop(guard);
+    line
+}
+
+/// Reads a line into a buffer which never grows.
+fn read_line<R: Read>(reader: &mut R, capacity: usize) -> io::Result<ClearOnDrop<ClearingString>> {
+    // Both buffers are cleared when dropped, including on errors.
+    let mut line = ClearingVec::with_capacity(capacity);
+    let mut byte = [0u8];
+    let mut byte = ClearOnDrop::new(&mut byte);
+    let mut too_long = false;
+    loop {
+        match reader.read(&mut byte[..]) {
+            Ok(0) if line.is_empty() && !too_long => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "end of input before the line",
+                ));
+            }
+            Ok(0) => break,
+            Ok(_) if byte[0] == b'\n' => break,
+            Ok(_) if line.len() < capacity => line.push(byte[0]),
+            Ok(_) => too_long = true,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    if too_long {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "line longer than the buffer",
+        ));
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    match ClearingString::from_utf8(line) {
+        Ok(line) => Ok(ClearOnDrop::new(line)),
+        Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+    }
+}
+
+/// Turns off the echo of a terminal, and turns it back on when dropped.
+struct EchoGuard {
+    fd: RawFd,
+    saved: libc::termios,
+}
+
+impl EchoGuard {
+    fn disable(fd: RawFd) -> io::Result<Self> {
+        unsafe {
+            let mut saved = MaybeUninit::<libc::termios>::uninit();
+            if libc::tcgetattr(fd, saved.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let saved = saved.assume_init();
+            let mut termios = saved;
+            // The newline is still echoed, so that the cursor moves to
+            // the next line once the line is entered.
+            termios.c_lflag &= !libc::ECHO;
+            termios.c_lflag |= libc::ECHONL;
+            if libc::tcsetattr(fd, libc::TCSAFLUSH, &termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(EchoGuard { fd, saved })
+        }
+    }
+}
+
+impl Drop for EchoGuard {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(self.fd, libc::TCSANOW, &self.saved);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_line;
+    use std::io;
+
+    #[test]
+    fn line() {
+        let mut input = &b"hunter2\r\nrest"[..];
+        let line = read_line(&mut input, 16).unwrap();
+        assert_eq!(&line[..], "hunter2");
+        assert_eq!(input, b"rest");
+        let line = read_line(&mut input, 16).unwrap();
+        assert_eq!(&line[..], "rest");
+    }
+
+    #[test]
+    fn capacity() {
+        let line = read_line(&mut &b"12345678\n"[..], 8).unwrap();
+        assert_eq!(&line[..], "12345678");
+
+        let mut input = &b"123456789\nrest\n"[..];
+        let err = read_line(&mut input, 8).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(input, b"rest\n");
+    }
+
+    #[test]
+    fn errors() {
+        let err = read_line(&mut &b""[..], 8).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        let line = read_line(&mut &b"\n"[..], 8).unwrap();
+        assert_eq!(&line[..], "");
+        let err = read_line(&mut &b"\xff\n"[..], 8).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,246 @@
+use core::any::Any;
+use core::mem::{self, MaybeUninit};
+use core::ptr;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+
+use crate::clear::Clear;
+use crate::pages::Pages;
+use crate::ClearOnDrop;
+
+/// Spawns a thread on a locked stack, which is cleared after it exits.
+///
+/// The memory used for the stack of a thread is freed (or cached for
+/// reuse) when the thread exits, without being overwritten, and it
+/// can't be overwritten from the outside with `clear_stack`. This
+/// function instead allocates the stack itself, from memory locked with
+/// `mlock` and with a guard page below it, runs `f` on the new thread,
+/// and overwrites the whole stack with zeros after the thread has been
+/// joined.
+///
+/// The result of `f` is written into a `ClearOnDrop<Box<R>>`, which is
+/// allocated before the thread is spawned, and returned by
+/// `ScrubbedJoinHandle::join`.
+///
+/// The `stack_size` is rounded up to a whole number of pages. The
+/// system might place the thread-local storage of the new thread at
+/// the top of the stack, which also gets cleared, but reduces the
+/// space left for `f`.
+///
+/// Returns an error if the stack could not be allocated or locked, or
+/// if the thread could not be created.
+///
+/// # Example
+///
+/// ```
+/// # use clear_on_drop::spawn_scrubbed;
+/// # fn encrypt(input: &[u8]) -> Vec<u8> { input.to_owned() }
+/// let handle = spawn_scrubbed(256 * 1024, || encrypt(b"abc")).unwrap();
+/// let result = handle.join().unwrap();
+/// assert_eq!(&result[..], b"abc");
+/// ```
+pub fn spawn_scrubbed<F, R>(stack_size: usize, f: F) -> io::Result<ScrubbedJoinHandle<R>>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Clear + Send + 'static,
+{
+    let stack = Pages::with_guard_pages(stack_size)?;
+    let result = Box::into_raw(Box::new(MaybeUninit::<R>::uninit()));
+    let packet = Box::into_raw(Box::new(Packet { f, result }));
+    unsafe {
+        let mut attr = MaybeUninit::<libc::pthread_attr_t>::uninit();
+        let mut rc = libc::pthread_attr_init(attr.as_mut_ptr());
+        let mut thread = MaybeUninit::<libc::pthread_t>::uninit();
+        if rc == 0 {
+            rc = libc::pthread_attr_setstack(
+                attr.as_mut_ptr(),
+                stack.as_ptr() as *mut libc::c_void,
+                stack.len(),
+            );
+            if rc == 0 {
+                rc = libc::pthread_create(
+                    thread.as_mut_ptr(),
+                    attr.as_ptr(),
+                    start::<F, R>,
+                    packet as *mut libc::c_void,
+                );
+            }
+            libc::pthread_attr_destroy(attr.as_mut_ptr());
+        }
+        if rc != 0 {
+            drop(Box::from_raw(packet));
+            drop(Box::from_raw(result));
+            return Err(io::Error::from_raw_os_error(rc));
+        }
+        Ok(ScrubbedJoinHandle {
+            thread: thread.assume_init(),
+            result,
+            stack: mem::ManuallyDrop::new(stack),
+        })
+    }
+}
+
+/// What is passed to the new thread.
+struct Packet<F, R> {
+    f: F,
+    result: *mut MaybeUninit<R>,
+}
+
+/// The entry point of the new thread.
+///
+/// Returns a null pointer on success, or a boxed panic payload.
+extern "C" fn start<F, R>(packet: *mut libc::c_void) -> *mut libc::c_void
+where
+    F: FnOnce() -> R,
+{
+    let packet = unsafe { Box::from_raw(packet as *mut Packet<F, R>) };
+    let Packet { f, result } = *packet;
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(value) => {
+            // Not accessed by the spawning thread until it's joined.
+            unsafe { (*result).write(value) };
+            ptr::null_mut()
+        }
+        Err(payload) => Box::into_raw(Box::new(payload)) as *mut libc::c_void,
+    }
+}
+
+/// An owned permission to join a thread spawned by `spawn_scrubbed`.
+///
+/// If the handle is dropped without calling `join`, the thread is
+/// still joined, and its stack cleared, when the handle is dropped.
+pub struct ScrubbedJoinHandle<R: Clear> {
+    thread: libc::pthread_t,
+    result: *mut MaybeUninit<R>,
+    // Freed only once the thread is known to have exited.
+    stack: mem::ManuallyDrop<Pages>,
+}
+
+impl<R: Clear> ScrubbedJoinHandle<R> {
+    /// Waits for the thread to finish, clears its stack, and returns
+    /// its result.
+    ///
+    /// If the thread panicked, returns the panic payload.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the thread can't be joined, in which case its stack
+    /// and the place for the result are leaked, since the thread might
+    /// still be using them.
+    pub fn join(self) -> thread::Result<ClearOnDrop<Box<R>>> {
+        let mut handle = mem::ManuallyDrop::new(self);
+        unsafe { handle.join_thread() }
+    }
+
+    /// Joins the thread, clears its stack, and takes ownership of the
+    /// result.
+    ///
+    /// Must be called only once.
+    unsafe fn join_thread(&mut self) -> thread::Result<ClearOnDrop<Box<R>>> {
+        let mut ret = ptr::null_mut();
+        let rc = libc::pthread_join(self.thread, &mut ret);
+        // The stack can't be freed if the thread might be still running.
+        if rc != 0 {
+            panic!(
+                "failed to join thread: {}",
+                io::Error::from_raw_os_error(rc)
+            );
+        }
+        // Clears the stack.
+        mem::ManuallyDrop::drop(&mut self.stack);
+        if ret.is_null() {
+            Ok(ClearOnDrop::new(Box::from_raw(self.result as *mut R)))
+        } else {
+            // The thread panicked before writing the result.
+            drop(Box::from_raw(self.result));
+            Err(*Box::from_raw(ret as *mut Box<dyn Any + Send>))
+        }
+    }
+}
+
+impl<R: Clear> Drop for ScrubbedJoinHandle<R> {
+    fn drop(&mut self) {
+        unsafe {
+            drop(self.join_thread());
+        }
+    }
+}
+
+// The result is only accessed after the thread has been joined.
+unsafe impl<R: Clear + Send> Send for ScrubbedJoinHandle<R> {}
+unsafe impl<R: Clear + Send> Sync for ScrubbedJoinHandle<R> {}
+
+#[cfg(test)]
+mod tests {
+    use super::spawn_scrubbed;
+    use crate::pages::skip_if_unlockable;
+
+    #[test]
+    fn result() {
+        let Some(handle) = skip_if_unlockable(spawn_scrubbed(32 * 1024, || [0x41u8; 32])) else {
+            return;
+        };
+        assert_eq!(*handle.join().unwrap(), [0x41; 32]);
+    }
+
+    #[test]
+    fn result_without_default() {
+        use crate::clear::Clear;
+
+        struct Key([u8; 64]);
+
+        impl Clear for Key {
+            fn clear(&mut self) {
+                self.0.clear();
+            }
+        }
+
+        let Some(handle) = skip_if_unlockable(spawn_scrubbed(32 * 1024, || Key([0x41; 64]))) else {
+            return;
+        };
+        assert_eq!(handle.join().unwrap().0, [0x41; 64]);
+    }
+
+    #[test]
+    fn panic() {
+        let handle = spawn_scrubbed(64 * 1024, || -> u32 { panic!("test") });
+        let Some(handle) = skip_if_unlockable(handle) else {
+            return;
+        };
+        let payload = handle.join().unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"test"));
+    }
+
+    #[test]
+    fn drop_without_join() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        let ran = Arc::new(AtomicBool::new(false));
+        let flag = ran.clone();
+        let handle = spawn_scrubbed(32 * 1024, move || flag.store(true, Ordering::SeqCst));
+        let Some(handle) = skip_if_unlockable(handle) else {
+            return;
+        };
+        drop(handle);
+        assert!(ran.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn deep_stack() {
+        fn recurse(depth: usize) -> u64 {
+            let buf = [depth as u8; 512];
+            let buf = crate::hide::hide_ptr(buf);
+            if depth == 0 {
+                buf[0] as u64
+            } else {
+                recurse(depth - 1) + buf[1] as u64
+            }
+        }
+        let Some(handle) = skip_if_unlockable(spawn_scrubbed(64 * 1024, || recurse(20))) else {
+            return;
+        };
+        assert_eq!(*handle.join().unwrap(), (0..=20).sum::<u64>());
+    }
+}
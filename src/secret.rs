@@ -0,0 +1,147 @@
+use alloc::boxed::Box;
+use core::fmt;
+
+use crate::clear::Clear;
+use crate::constant_time::ConstantTimeEq;
+use crate::ClearOnDrop;
+
+/// An owned secret value, which is cleared when dropped.
+///
+/// A `ClearOnDrop<Box<T>>` behaves like the value it contains, which
+/// makes it easy to leak the value by accident: its `Debug`
+/// implementation prints the value, it can be cloned, and it can be
+/// used anywhere a `&T` is expected. A `Secret<T>` stores the value the
+/// same way, in a `ClearOnDrop<Box<T>>`, but prints `[REDACTED]`
+/// instead of the value, cannot be cloned, and gives access to the
+/// value only through explicit calls to `expose_secret` and
+/// `expose_secret_mut`.
+///
+/// When the value implements `ConstantTimeEq`, comparing two secrets
+/// with `==` uses it, so the time taken does not depend on where they
+/// differ.
+///
+/// # Example
+///
+/// ```
+/// # use clear_on_drop::Secret;
+/// let key = Secret::new([0x41u8; 32]);
+/// assert_eq!(format!("{:?}", key), "Secret([REDACTED])");
+/// assert_eq!(key.expose_secret()[0], 0x41);
+/// ```
+pub struct Secret<T: ?Sized + Clear> {
+    inner: ClearOnDrop<Box<T>>,
+}
+
+impl<T: Clear> Secret<T> {
+    /// Moves `value` to the heap, and wraps it as a `Secret`.
+    ///
+    /// Note: the `value` might be left behind on the stack, since it's
+    /// moved from there to the heap. Use `Secret::default` followed by
+    /// `expose_secret_mut` to build the value in place.
+    #[inline]
+    pub fn new(value: T) -> Self {
+        Self::from_box(Box::new(value))
+    }
+}
+
+impl<T: ?Sized + Clear> Secret<T> {
+    /// Wraps a value which is already on the heap as a `Secret`.
+    #[inline]
+    pub fn from_box(value: Box<T>) -> Self {
+        Secret {
+            inner: ClearOnDrop::new(value),
+        }
+    }
+
+    /// Returns a reference to the secret value.
+    #[inline]
+    pub fn expose_secret(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the secret value.
+    #[inline]
+    pub fn expose_secret_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<T: Clear + Default> Default for Secret<T> {
+    #[inline]
+    fn default() -> Self {
+        Self::from_box(Box::default())
+    }
+}
+
+impl<T: ?Sized + Clear> From<Box<T>> for Secret<T> {
+    #[inline]
+    fn from(value: Box<T>) -> Self {
+        Self::from_box(value)
+    }
+}
+
+impl<T: ?Sized + Clear + ConstantTimeEq> ConstantTimeEq for Secret<T> {
+    #[inline]
+    fn ct_eq(&self, other: &Self) -> bool {
+        self.expose_secret().ct_eq(other.expose_secret())
+    }
+}
+
+impl<T: ?Sized + Clear + ConstantTimeEq> PartialEq for Secret<T> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other)
+    }
+}
+
+impl<T: ?Sized + Clear + ConstantTimeEq> Eq for Secret<T> {}
+
+impl<T: ?Sized + Clear> fmt::Debug for Secret<T> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Secret([REDACTED])")
+    }
+}
+
+impl<T: ?Sized + Clear> fmt::Display for Secret<T> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Secret;
+
+    #[test]
+    fn redacted() {
+        let secret = Secret::new(0x41414141u32);
+        assert_eq!(format!("{:?}", secret), "Secret([REDACTED])");
+        assert_eq!(format!("{}", secret), "[REDACTED]");
+        assert_eq!(format!("{:?}", (1, &secret)), "(1, Secret([REDACTED]))");
+    }
+
+    #[test]
+    fn expose() {
+        let mut secret: Secret<[u8; 4]> = Secret::default();
+        secret.expose_secret_mut().copy_from_slice(b"test");
+        assert_eq!(secret.expose_secret(), b"test");
+    }
+
+    #[test]
+    fn eq() {
+        let a = Secret::new([0x41u8; 16]);
+        let mut b = Secret::new([0x41u8; 16]);
+        assert_eq!(a, b);
+        b.expose_secret_mut()[0] = 0;
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn boxed_slice() {
+        let secret: Secret<[u8]> = Secret::from(vec![1, 2, 3].into_boxed_slice());
+        assert_eq!(secret.expose_secret(), [1, 2, 3]);
+        assert_eq!(format!("{:?}", secret), "Secret([REDACTED])");
+    }
+}
@@ -0,0 +1,226 @@
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+
+use crate::hide::hide_ptr;
+use crate::pages::Pages;
+
+/// Calls a closure on a separate stack, which is cleared on return.
+///
+/// Instead of overwriting a guessed number of pages of the current
+/// stack, as `clear_stack_on_return` does, this function allocates a
+/// new stack of `size` bytes (rounded up to a whole number of pages),
+/// from memory locked with `mlock` and surrounded by guard pages. It
+/// then switches the stack pointer to it, calls the closure, switches
+/// back, and overwrites the whole new stack with zeros before freeing
+/// it. Everything the closure left on its stack is cleared, no matter
+/// how deep it went.
+///
+/// If the closure uses more than `size` bytes of stack, it hits the
+/// guard page, and the process is killed by a segmentation fault. A
+/// panic in the closure is caught on the new stack, and resumed after
+/// the stack has been cleared.
+///
+/// Returns an error if the stack could not be allocated or locked.
+///
+/// This function is only available on Linux, on x86-64 and AArch64.
+///
+/// # Example
+///
+/// ```
+/// # use clear_on_drop::with_secret_stack;
+/// # fn encrypt(input: &[u8]) -> Vec<u8> { input.to_owned() }
+/// let input = b"abc";
+/// let result = with_secret_stack(64 * 1024, || encrypt(input)).unwrap();
+/// ```
+pub fn with_secret_stack<F, R>(size: usize, f: F) -> io::Result<R>
+where
+    F: FnOnce() -> R,
+{
+    let stack = Pages::with_guard_pages(size)?;
+    let mut data = Data::<F, R> {
+        f: Some(f),
+        result: None,
+    };
+    unsafe {
+        // The top of the stack is page-aligned, which is more than what
+        // both architectures require.
+        let top = stack.as_ptr().add(stack.len());
+        // Do not inline the closure into the caller's stack frame.
+        let start = hide_ptr::<extern "C" fn(*mut u8)>(start::<F, R>);
+        imp::call_on_stack(top, start, &mut data as *mut Data<F, R> as *mut u8);
+    }
+    drop(stack);
+    match data.result.take().expect("closure did not return") {
+        Ok(result) => Ok(result),
+        Err(payload) => panic::resume_unwind(payload),
+    }
+}
+
+/// What is passed to the new stack.
+struct Data<F, R> {
+    f: Option<F>,
+    result: Option<thread::Result<R>>,
+}
+
+/// The first function called on the new stack.
+///
+/// Unwinding must not reach the stack switching code, so panics are
+/// caught here.
+extern "C" fn start<F, R>(data: *mut u8)
+where
+    F: FnOnce() -> R,
+{
+    let data = unsafe { &mut *(data as *mut Data<F, R>) };
+    let f = data.f.take().expect("closure called twice");
+    data.result = Some(panic::catch_unwind(AssertUnwindSafe(f)));
+}
+
+// Naked functions need a newer compiler, so the stack switching code
+// is written as global assembly, and declared as an external function.
+// Its name includes the version of the crate, so that semver-incompatible
+// versions linked into the same program don't define the same symbol; in
+// the assembly, it's quoted, since the version contains dots.
+macro_rules! symbol {
+    () => {
+        concat!("clear_on_drop_call_on_stack_", env!("CARGO_PKG_VERSION"))
+    };
+    (quoted) => {
+        concat!("\"", symbol!(), "\"")
+    };
+}
+
+#[cfg(target_arch = "x86_64")]
+mod imp {
+    use core::arch::global_asm;
+
+    extern "C" {
+        /// Calls `f(data)` with the stack pointer set to `top`.
+        ///
+        /// The frame pointer keeps the caller's stack pointer, and the call
+        /// frame information describes it, so that stack unwinders (used to
+        /// print backtraces) can walk back to the original stack.
+        #[link_name = symbol!()]
+        pub fn call_on_stack(top: *mut u8, f: extern "C" fn(*mut u8), data: *mut u8);
+    }
+
+    global_asm!(
+        ".pushsection .text",
+        concat!(".globl ", symbol!(quoted)),
+        concat!(".hidden ", symbol!(quoted)),
+        concat!(".type ", symbol!(quoted), ", %function"),
+        ".p2align 4",
+        concat!(symbol!(quoted), ":"),
+        ".cfi_startproc",
+        "push rbp",
+        ".cfi_def_cfa_offset 16",
+        ".cfi_offset rbp, -16",
+        "mov rbp, rsp",
+        ".cfi_def_cfa_register rbp",
+        "mov rsp, rdi",
+        "mov rdi, rdx",
+        "call rsi",
+        "mov rsp, rbp",
+        ".cfi_def_cfa_register rsp",
+        "pop rbp",
+        ".cfi_def_cfa_offset 8",
+        "ret",
+        ".cfi_endproc",
+        concat!(".size ", symbol!(quoted), ", . - ", symbol!(quoted)),
+        ".popsection",
+    );
+}
+
+#[cfg(target_arch = "aarch64")]
+mod imp {
+    use core::arch::global_asm;
+
+    extern "C" {
+        /// Calls `f(data)` with the stack pointer set to `top`.
+        ///
+        /// The frame pointer keeps the caller's stack pointer, and the call
+        /// frame information describes it, so that stack unwinders (used to
+        /// print backtraces) can walk back to the original stack.
+        #[link_name = symbol!()]
+        pub fn call_on_stack(top: *mut u8, f: extern "C" fn(*mut u8), data: *mut u8);
+    }
+
+    global_asm!(
+        ".pushsection .text",
+        concat!(".globl ", symbol!(quoted)),
+        concat!(".hidden ", symbol!(quoted)),
+        concat!(".type ", symbol!(quoted), ", %function"),
+        ".p2align 2",
+        concat!(symbol!(quoted), ":"),
+        ".cfi_startproc",
+        "stp x29, x30, [sp, #-16]!",
+        ".cfi_def_cfa_offset 16",
+        ".cfi_offset x30, -8",
+        ".cfi_offset x29, -16",
+        "mov x29, sp",
+        ".cfi_def_cfa_register x29",
+        "mov sp, x0",
+        "mov x0, x2",
+        "blr x1",
+        "mov sp, x29",
+        ".cfi_def_cfa_register sp",
+        "ldp x29, x30, [sp], #16",
+        ".cfi_def_cfa_offset 0",
+        ".cfi_restore x30",
+        ".cfi_restore x29",
+        "ret",
+        ".cfi_endproc",
+        concat!(".size ", symbol!(quoted), ", . - ", symbol!(quoted)),
+        ".popsection",
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::with_secret_stack;
+    use crate::hide::hide_ptr;
+    use crate::pages::skip_if_unlockable;
+    use std::panic;
+
+    #[inline(never)]
+    fn recurse(depth: usize) -> u64 {
+        let buf = hide_ptr([depth as u8; 512]);
+        if depth == 0 {
+            buf[0] as u64
+        } else {
+            recurse(depth - 1) + buf[1] as u64
+        }
+    }
+
+    #[test]
+    fn result() {
+        let input = [0x41u8; 32];
+        let Some(result) = skip_if_unlockable(with_secret_stack(16 * 1024, || input.to_vec()))
+        else {
+            return;
+        };
+        assert_eq!(result, input);
+    }
+
+    #[test]
+    fn deep_stack() {
+        let Some(result) = skip_if_unlockable(with_secret_stack(64 * 1024, || recurse(20))) else {
+            return;
+        };
+        assert_eq!(result, (0..=20).sum::<u64>());
+    }
+
+    #[test]
+    fn panic() {
+        let result =
+            panic::catch_unwind(|| with_secret_stack(32 * 1024, || -> u32 { panic!("test") }));
+        let payload = match result {
+            Ok(result) => {
+                assert!(skip_if_unlockable(result).is_none());
+                return;
+            }
+            Err(payload) => payload,
+        };
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"test"));
+    }
+}
@@ -0,0 +1,161 @@
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+use std::io;
+
+use crate::pages::{page_size, Pages};
+
+/// A pointer type for heap allocation in locked memory.
+///
+/// The value is stored in memory allocated directly from the operating
+/// system, which is locked with `mlock` so that it will not be written
+/// to swap, and excluded from core dumps where possible. When dropped,
+/// the whole allocation is overwritten with zeros before being freed.
+///
+/// Since each `SecureBox` uses at least one page of locked memory, and
+/// the amount of locked memory is usually limited, it should be used
+/// only for small amounts of sensitive data.
+///
+/// It behaves like a `Box<T>`, so it can be used as the place of a
+/// `ClearOnDrop`.
+///
+/// # Example
+///
+/// ```
+/// # use clear_on_drop::{ClearOnDrop, SecureBox};
+/// #[derive(Default)]
+/// struct MyData {
+///     value: u32,
+/// }
+///
+/// let place: SecureBox<MyData> = SecureBox::default();
+/// let mut key = ClearOnDrop::new(place);
+/// key.value = 0x01234567;
+/// ```
+pub struct SecureBox<T> {
+    pages: Pages,
+    _marker: PhantomData<T>,
+}
+
+impl<T> SecureBox<T> {
+    /// Allocates locked memory and places `value` into it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the memory could not be allocated or locked.
+    #[inline]
+    pub fn new(value: T) -> Self {
+        match Self::try_new(value) {
+            Ok(b) => b,
+            Err(err) => panic!("failed to allocate locked memory: {}", err),
+        }
+    }
+
+    /// Allocates locked memory and places `value` into it, returning
+    /// an error if the memory could not be allocated or locked.
+    pub fn try_new(value: T) -> io::Result<Self> {
+        assert!(mem::align_of::<T>() <= page_size());
+        let pages = Pages::new(mem::size_of::<T>())?;
+        unsafe {
+            ptr::write(pages.as_ptr() as *mut T, value);
+        }
+        Ok(SecureBox {
+            pages,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<T: Default> Default for SecureBox<T> {
+    #[inline]
+    fn default() -> Self {
+        SecureBox::new(Default::default())
+    }
+}
+
+impl<T> Deref for SecureBox<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        unsafe { &*(self.pages.as_ptr() as *const T) }
+    }
+}
+
+impl<T> DerefMut for SecureBox<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *(self.pages.as_ptr() as *mut T) }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for SecureBox<T> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T> Drop for SecureBox<T> {
+    #[inline]
+    fn drop(&mut self) {
+        // The pages are cleared when dropped, after this.
+        unsafe {
+            ptr::drop_in_place(self.pages.as_ptr() as *mut T);
+        }
+    }
+}
+
+unsafe impl<T: Send> Send for SecureBox<T> {}
+unsafe impl<T: Sync> Sync for SecureBox<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::SecureBox;
+    use crate::ClearOnDrop;
+    use std::rc::Rc;
+
+    #[derive(Debug, Default)]
+    struct Place {
+        data: [u32; 4],
+    }
+
+    const DATA: [u32; 4] = [0x01234567, 0x89abcdef, 0xfedcba98, 0x76543210];
+
+    #[test]
+    fn new() {
+        let place = SecureBox::new(Place { data: DATA });
+        assert_eq!(place.data, DATA);
+    }
+
+    #[test]
+    fn is_aligned() {
+        let place = SecureBox::new(0u64);
+        assert_eq!(
+            &*place as *const u64 as usize % crate::pages::page_size(),
+            0
+        );
+    }
+
+    #[test]
+    fn drops_value() {
+        let rc = Rc::new(());
+        let place = SecureBox::new(Rc::clone(&rc));
+        assert_eq!(Rc::strong_count(&rc), 2);
+        drop(place);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn on_clear_on_drop() {
+        let place: SecureBox<Place> = SecureBox::default();
+        let mut clear = ClearOnDrop::new(place);
+        clear.data = DATA;
+        assert_eq!(clear.data, DATA);
+
+        let place = ClearOnDrop::into_place(clear);
+        assert_eq!(place.data, [0; 4]);
+    }
+}
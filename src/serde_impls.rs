@@ -0,0 +1,247 @@
+//! Integration with `serde`.
+//!
+//! Deserializing a value usually builds it on the stack, and then moves
+//! it to where it will be kept, leaving a copy behind. A
+//! `ClearOnDrop<Box<T>>` is instead deserialized in place, directly into
+//! the box, through `Deserialize::deserialize_in_place` (which is
+//! implemented without intermediate copies by arrays, among others).
+//! Byte and string buffers are collected into buffers which are cleared
+//! when they grow or are dropped.
+//!
+//! Serializing a secret is seldom what is wanted, so it has to be
+//! enabled for each type by implementing `SerializableSecret`.
+//!
+//! The deserializer itself might keep copies of the data, either in the
+//! input or in its own buffers (for instance, when decoding escape
+//! sequences in strings); those have to be cleared separately.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::DerefMut;
+
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use crate::clear::Clear;
+use crate::clearing_vec::clear_spare;
+use crate::{ClearOnDrop, ClearingString, ClearingVec, Secret};
+
+/// Marker trait for secrets which can be serialized.
+///
+/// A `ClearOnDrop<P>` or a `Secret<T>` implements `Serialize` only when
+/// the type of the value implements this trait, so that secrets are not
+/// written out by accident.
+///
+/// # Example
+///
+/// ```
+/// # use clear_on_drop::{ClearOnDrop, SerializableSecret};
+/// #[derive(Default, serde::Serialize)]
+/// struct ExportableKey([u8; 32]);
+///
+/// impl SerializableSecret for ExportableKey {}
+///
+/// let key = ClearOnDrop::new(Box::new(ExportableKey([0x41; 32])));
+/// let json = serde_json::to_string(&key).unwrap();
+/// ```
+pub trait SerializableSecret {}
+
+impl<P> Serialize for ClearOnDrop<P>
+where
+    P: DerefMut,
+    P::Target: Clear + Serialize + SerializableSecret,
+{
+    #[inline]
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (**self).serialize(serializer)
+    }
+}
+
+impl<T> Serialize for Secret<T>
+where
+    T: ?Sized + Clear + Serialize + SerializableSecret,
+{
+    #[inline]
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.expose_secret().serialize(serializer)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for ClearOnDrop<Box<T>>
+where
+    T: Deserialize<'de> + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // On error, the partially deserialized value is cleared.
+        let mut place = ClearOnDrop::new(Box::<T>::default());
+        T::deserialize_in_place(deserializer, &mut place)?;
+        Ok(place)
+    }
+}
+
+impl<'de> Deserialize<'de> for ClearOnDrop<Box<[u8]>> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let vec = deserializer.deserialize_byte_buf(BytesVisitor)?;
+        Ok(ClearOnDrop::new(Box::from(&vec[..])))
+    }
+}
+
+impl<'de> Deserialize<'de> for ClearOnDrop<Box<str>> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let string = deserializer.deserialize_string(StrVisitor)?;
+        Ok(ClearOnDrop::new(Box::from(&string[..])))
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Secret<T>
+where
+    T: Deserialize<'de> + Default,
+{
+    #[inline]
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let place = ClearOnDrop::<Box<T>>::deserialize(deserializer)?;
+        Ok(Secret::from_box(ClearOnDrop::into_uncleared_place(place)))
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret<[u8]> {
+    #[inline]
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let place = ClearOnDrop::<Box<[u8]>>::deserialize(deserializer)?;
+        Ok(Secret::from_box(ClearOnDrop::into_uncleared_place(place)))
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret<str> {
+    #[inline]
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let place = ClearOnDrop::<Box<str>>::deserialize(deserializer)?;
+        Ok(Secret::from_box(ClearOnDrop::into_uncleared_place(place)))
+    }
+}
+
+/// Clears a buffer given by the deserializer, including its spare
+/// capacity, before it's freed.
+#[inline]
+fn clear_vec(mut vec: Vec<u8>) {
+    clear_spare(&mut vec, 0);
+}
+
+/// Collects bytes into a `ClearingVec<u8>`.
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = ClearingVec<u8>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a byte array")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        let mut vec = ClearingVec::with_capacity(v.len());
+        vec.extend_from_slice(v);
+        Ok(vec)
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        let vec = self.visit_bytes(&v);
+        clear_vec(v);
+        vec
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        self.visit_bytes(v.as_bytes())
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+        self.visit_byte_buf(v.into_bytes())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        // On error, the bytes collected so far are cleared.
+        let mut vec = ClearingVec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(b) = seq.next_element()? {
+            vec.push(b);
+        }
+        Ok(vec)
+    }
+}
+
+/// Collects a string into a `ClearingString`.
+struct StrVisitor;
+
+impl<'de> Visitor<'de> for StrVisitor {
+    type Value = ClearingString;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a string")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        let mut string = ClearingString::with_capacity(v.len());
+        string.push_str(v);
+        Ok(string)
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+        let string = self.visit_str(&v);
+        clear_vec(v.into_bytes());
+        string
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        match core::str::from_utf8(v) {
+            Ok(s) => self.visit_str(s),
+            Err(_) => Err(E::invalid_value(de::Unexpected::Bytes(v), &self)),
+        }
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        let string = self.visit_bytes(&v);
+        clear_vec(v);
+        string
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SerializableSecret;
+    use crate::{ClearOnDrop, Secret};
+
+    #[test]
+    fn array() {
+        let key: ClearOnDrop<Box<[u8; 4]>> = serde_json::from_str("[1, 2, 3, 4]").unwrap();
+        assert_eq!(*key, [1, 2, 3, 4]);
+        assert!(serde_json::from_str::<ClearOnDrop<Box<[u8; 4]>>>("[1, 2, 3]").is_err());
+    }
+
+    #[test]
+    fn bytes() {
+        let key: ClearOnDrop<Box<[u8]>> = serde_json::from_str("[1, 2, 3]").unwrap();
+        assert_eq!(&key[..], [1, 2, 3]);
+        let key: Secret<[u8]> = serde_json::from_str("\"abc\"").unwrap();
+        assert_eq!(key.expose_secret(), b"abc");
+    }
+
+    #[test]
+    fn string() {
+        let password: ClearOnDrop<Box<str>> = serde_json::from_str("\"hunter\\u0032\"").unwrap();
+        assert_eq!(&password[..], "hunter2");
+        let password: Secret<str> = serde_json::from_str("\"hunter2\"").unwrap();
+        assert_eq!(password.expose_secret(), "hunter2");
+    }
+
+    #[test]
+    fn serialize() {
+        #[derive(Default, serde::Serialize, serde::Deserialize)]
+        struct Exportable(u32);
+        impl SerializableSecret for Exportable {}
+
+        let key = Secret::new(Exportable(42));
+        assert_eq!(serde_json::to_string(&key).unwrap(), "42");
+        let key: Secret<Exportable> = serde_json::from_str("43").unwrap();
+        assert_eq!(key.expose_secret().0, 43);
+    }
+}
@@ -0,0 +1,242 @@
+//! Measure how much stack a closure uses, to size `clear_stack`.
+//!
+//! Before calling the closure, the stack below the current frame is
+//! painted with a sentinel value. After it returns, the painted area is
+//! scanned to find the deepest point where the sentinel was overwritten.
+//!
+//! The painting and scanning read and write memory below the stack
+//! pointer, which is outside of what the language allows; this is done
+//! only through volatile accesses, and only to memory which has just
+//! been used by `clear_stack`. It assumes the stack grows downwards,
+//! which is the case on all common architectures.
+
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::clear_stack_on_return::{clear_stack, clear_stack_on_return, ClearStackOnDrop};
+use crate::hide::hide_ptr;
+
+const PAGE_SIZE: usize = 4096;
+
+// The area just below the painting and scanning functions is left
+// alone, since it's used by their own calls.
+const SLACK: usize = 4096;
+
+#[cfg(target_pointer_width = "64")]
+const SENTINEL: usize = 0xa5c3_5a3c_a5c3_5a3c;
+#[cfg(not(target_pointer_width = "64"))]
+const SENTINEL: usize = 0xa5c3_5a3c;
+
+/// Calls a closure, measures how much stack it used, and overwrites
+/// its stack on return.
+///
+/// Returns the closure's result, and the number of 4096-byte pages of
+/// stack which have to be overwritten to clear everything it used; this
+/// is the value to be passed as `pages` to `clear_stack_on_return`. If
+/// the returned number of pages is equal to `max_pages`, the closure
+/// might have used even more stack, and the measurement should be
+/// repeated with a larger `max_pages`.
+///
+/// The stack usage of a closure can depend on its inputs, and on which
+/// paths it takes; measure it with representative inputs, and consider
+/// adding a safety margin.
+///
+/// # Safety
+///
+/// The measurement reads and writes memory below the stack pointer,
+/// which is outside of what the language allows, and is only a
+/// best-effort diagnostic. The stack must grow downwards, and have room
+/// for `max_pages` pages below the current stack frame.
+///
+/// # Example
+///
+/// ```
+/// # use clear_on_drop::measure_stack_on_return;
+/// # fn encrypt(input: &[u8]) -> Vec<u8> { input.to_owned() }
+/// let input = b"abc";
+/// let (result, pages) = unsafe { measure_stack_on_return(16, || encrypt(input)) };
+/// assert!(pages >= 1);
+/// ```
+#[inline]
+pub unsafe fn measure_stack_on_return<F, R>(max_pages: usize, mut f: F) -> (R, usize)
+where
+    F: FnMut() -> R,
+{
+    let base = 0u8;
+    let base = &base as *const u8 as usize;
+    let _clear = ClearStackOnDrop {
+        pages: max_pages,
+        registers: false,
+    };
+    hide_ptr::<fn(usize, usize)>(paint_stack)(base, max_pages);
+    // Do not inline f to make sure it uses the painted stack space.
+    let result = hide_ptr::<&mut dyn FnMut() -> R>(&mut f)();
+    let used = hide_ptr::<fn(usize, usize) -> usize>(measure_stack)(base, max_pages);
+    let pages = (used + PAGE_SIZE - 1) / PAGE_SIZE;
+    (result, pages.max(1).min(max_pages))
+}
+
+/// Fills `pages` pages of stack below `base` with the sentinel.
+#[inline(never)]
+fn paint_stack(base: usize, pages: usize) {
+    // Make sure the stack pages exist, without moving the stack pointer.
+    clear_stack(pages);
+
+    let (bottom, top) = painted_area(base, pages);
+    let mut p = bottom as *mut usize;
+    while (p as usize) < top {
+        unsafe {
+            ptr::write_volatile(p, SENTINEL);
+            p = p.add(1);
+        }
+    }
+}
+
+/// Returns the aligned bounds of the area to paint or scan, which must
+/// be below the caller's own stack frame.
+#[inline(always)]
+fn painted_area(base: usize, pages: usize) -> (usize, usize) {
+    let align = mem::align_of::<usize>();
+    let frame = 0u8;
+    let top = (&frame as *const u8 as usize).saturating_sub(SLACK) & !(align - 1);
+    let bottom = base.saturating_sub(pages * PAGE_SIZE) & !(align - 1);
+    (bottom, top)
+}
+
+/// Returns how many bytes below `base` the sentinel was overwritten.
+#[inline(never)]
+fn measure_stack(base: usize, pages: usize) -> usize {
+    let (bottom, top) = painted_area(base, pages);
+    let mut p = bottom as *const usize;
+    while (p as usize) < top {
+        if unsafe { ptr::read_volatile(p) } != SENTINEL {
+            break;
+        }
+        p = unsafe { p.add(1) };
+    }
+    base.saturating_sub(p as usize)
+}
+
+/// A cached measurement of how much stack a closure uses.
+///
+/// The first call to `StackCalibration::clear_stack_on_return` measures
+/// the stack used by the closure, through `measure_stack_on_return`.
+/// Later calls use the measured number of pages, which avoids both the
+/// cost of the measurement and the cost of clearing more stack than
+/// necessary.
+///
+/// It's meant to be kept in a `static`, one for each operation whose
+/// stack has to be cleared.
+///
+/// # Example
+///
+/// ```
+/// # use clear_on_drop::StackCalibration;
+/// # fn encrypt(input: &[u8]) -> Vec<u8> { input.to_owned() }
+/// static ENCRYPT_STACK: StackCalibration = StackCalibration::new();
+///
+/// let input = b"abc";
+/// let result = unsafe { ENCRYPT_STACK.clear_stack_on_return(16, || encrypt(input)) };
+/// assert!(ENCRYPT_STACK.pages().is_some());
+/// ```
+#[derive(Debug, Default)]
+pub struct StackCalibration {
+    pages: AtomicUsize,
+}
+
+impl StackCalibration {
+    /// Creates a new, not yet measured, `StackCalibration`.
+    #[inline]
+    pub const fn new() -> Self {
+        StackCalibration {
+            pages: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the measured number of pages, if it has been measured.
+    #[inline]
+    pub fn pages(&self) -> Option<usize> {
+        match self.pages.load(Ordering::Relaxed) {
+            0 => None,
+            pages => Some(pages),
+        }
+    }
+
+    /// Calls a closure and overwrites its stack on return.
+    ///
+    /// On the first call, this measures the stack used by the closure,
+    /// painting and clearing up to `max_pages` pages. Later calls clear
+    /// only the measured number of pages.
+    ///
+    /// # Safety
+    ///
+    /// The first call measures the stack through
+    /// `measure_stack_on_return`, and has the same requirements.
+    #[inline]
+    pub unsafe fn clear_stack_on_return<F, R>(&self, max_pages: usize, f: F) -> R
+    where
+        F: FnMut() -> R,
+    {
+        match self.pages() {
+            Some(pages) => clear_stack_on_return(pages, f),
+            None => {
+                let (result, pages) = measure_stack_on_return(max_pages, f);
+                self.pages.store(pages, Ordering::Relaxed);
+                result
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{measure_stack_on_return, StackCalibration};
+    use core::ptr;
+
+    #[inline(never)]
+    fn use_stack(bytes: usize) -> u8 {
+        let mut buf = [0u8; 4096];
+        // Volatile writes can't be elided, even without an asm barrier.
+        for b in &mut buf[..] {
+            unsafe { ptr::write_volatile(b, 0) };
+        }
+        if bytes > buf.len() {
+            use_stack(bytes - buf.len()) ^ buf[0]
+        } else {
+            buf[0]
+        }
+    }
+
+    #[test]
+    fn small() {
+        let (_, pages) = unsafe { measure_stack_on_return(16, || use_stack(1)) };
+        assert!(pages >= 1);
+        assert!(pages <= 4);
+    }
+
+    #[test]
+    fn large() {
+        let (_, pages) = unsafe { measure_stack_on_return(32, || use_stack(8 * 4096)) };
+        assert!(pages >= 8);
+        assert!(pages < 32);
+    }
+
+    #[test]
+    fn saturated() {
+        let (_, pages) = unsafe { measure_stack_on_return(4, || use_stack(8 * 4096)) };
+        assert_eq!(pages, 4);
+    }
+
+    #[test]
+    fn calibration() {
+        let calibration = StackCalibration::new();
+        assert_eq!(calibration.pages(), None);
+        let result = unsafe { calibration.clear_stack_on_return(32, || use_stack(4 * 4096)) };
+        assert_eq!(result, 0);
+        let pages = calibration.pages().unwrap();
+        assert!(pages >= 4);
+        unsafe { calibration.clear_stack_on_return(32, || use_stack(4 * 4096)) };
+        assert_eq!(calibration.pages(), Some(pages));
+    }
+}
@@ -0,0 +1,535 @@
+//! Low-level functions to overwrite large amounts of memory.
+//!
+//! The `Clear` trait overwrites a value using `ptr::write_bytes`, which
+//! is the best choice for small and medium values. For large slices,
+//! the functions in this module can be faster, by using wide vector
+//! stores selected at runtime (when the `std` feature is enabled) or at
+//! compile time, and optionally non-temporal stores which bypass the
+//! cache.
+//!
+//! Memory which has been overwritten might still have its old contents
+//! in main memory for a while, until the cache lines holding the new
+//! contents are written back. The `flush` function explicitly writes
+//! back and evicts these cache lines.
+//!
+//! Finally, some policies require memory to be overwritten with other
+//! patterns before the final zeros. The `WipePattern` type describes
+//! these patterns, which can be used with `Clear::clear_with` and
+//! `clear_stack_with`.
+//!
+//! # Examples
+//!
+//! ```
+//! # use clear_on_drop::wipe::{clear_slice, Stores};
+//! let mut state = vec![0x41u64; 65536];
+//! clear_slice(&mut state, Stores::NonTemporal);
+//! assert!(state.iter().all(|&x| x == 0));
+//! ```
+
+use core::fmt;
+use core::mem;
+use core::ptr;
+use core::slice;
+
+use crate::clear::ZeroSafe;
+use crate::hide::hide_mem_impl;
+
+/// The kind of memory stores used to overwrite memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stores {
+    /// Normal stores, which go through the cache. This is the fastest
+    /// choice unless the memory is much larger than the cache.
+    Regular,
+    /// Non-temporal stores, which bypass the cache, followed by a store
+    /// fence. This avoids evicting other data from the cache when
+    /// clearing very large amounts of memory, and sends the zeros
+    /// towards main memory without waiting for them to be evicted.
+    ///
+    /// Falls back to regular stores on architectures without them.
+    NonTemporal,
+}
+
+/// Overwrites a slice with zeros, using wide vector stores.
+///
+/// Like `Clear::clear`, this cannot be optimized away. Unlike it, the
+/// elements are not dropped, which is why they must be `ZeroSafe`.
+#[inline]
+pub fn clear_slice<T: ZeroSafe>(slice: &mut [T], stores: Stores) {
+    unsafe {
+        zero_bytes(
+            slice.as_mut_ptr() as *mut u8,
+            mem::size_of_val(slice),
+            stores,
+        );
+    }
+    hide_mem_impl::<[T]>(slice);
+}
+
+/// Flushes the memory occupied by `value` from the cache hierarchy.
+///
+/// This writes back to main memory and evicts every cache line which
+/// holds part of `value`, using `clflushopt` or `clflush` on x86 and
+/// `dc civac` on aarch64. It does nothing on other architectures.
+///
+/// It's most useful after overwriting sensitive data, to make sure the
+/// new contents reach main memory as soon as possible; see
+/// `Clear::clear_and_flush` and `ClearAndFlushOnDrop`.
+#[inline]
+pub fn flush<T: ?Sized>(value: &T) {
+    unsafe { flush_bytes(value as *const T as *const u8, mem::size_of_val(value)) }
+}
+
+/// Flushes `len` bytes at `ptr` from the cache hierarchy.
+#[inline]
+pub(crate) unsafe fn flush_bytes(ptr: *const u8, len: usize) {
+    if len > 0 {
+        arch::flush(ptr, len);
+    }
+}
+
+/// A pattern used to overwrite memory before it is set to zero.
+///
+/// Whatever the pattern, the memory is always set to all-bits-zero at
+/// the end, since that's what `InitializableFromZeroed` expects.
+///
+/// # Example
+///
+/// ```
+/// # use clear_on_drop::clear::Clear;
+/// # use clear_on_drop::wipe::WipePattern;
+/// # use std::cell::Cell;
+/// // A toy generator; use a real random number generator instead.
+/// let state = Cell::new(0x2545f491u32);
+/// let random = |buf: &mut [u8]| {
+///     for b in buf {
+///         let x = state.get() ^ (state.get() << 13);
+///         let x = x ^ (x >> 17);
+///         state.set(x ^ (x << 5));
+///         *b = x as u8;
+///     }
+/// };
+/// let passes = [WipePattern::Ones, WipePattern::Fill(&random)];
+///
+/// let mut key = [0x41u8; 32];
+/// key.clear_with(&WipePattern::Passes(&passes));
+/// assert_eq!(key, [0; 32]);
+/// ```
+#[derive(Clone, Copy)]
+pub enum WipePattern<'a> {
+    /// All-bits-zero. Since the memory is set to zero at the end anyway,
+    /// this is the same as not using a pattern.
+    Zero,
+    /// All-bits-one.
+    Ones,
+    /// The given byte, repeated.
+    Byte(u8),
+    /// Calls a function to fill the memory, for instance with random
+    /// bytes. The function must not read from the buffer.
+    ///
+    /// A random number generator usually needs to be mutable, so it has
+    /// to be wrapped in a `Cell` or `RefCell`.
+    Fill(&'a dyn Fn(&mut [u8])),
+    /// Each of the given patterns in sequence, one pass for each.
+    Passes(&'a [WipePattern<'a>]),
+}
+
+impl<'a> WipePattern<'a> {
+    /// Overwrites `len` bytes at `ptr` with this pattern.
+    ///
+    /// Each pass is hidden from the optimizer, so that it will not be
+    /// elided even though it's overwritten by the next pass.
+    ///
+    /// The memory must be initialized, since a `Fill` function gets it
+    /// as a `&mut [u8]`.
+    pub(crate) unsafe fn overwrite(&self, ptr: *mut u8, len: usize) {
+        match *self {
+            WipePattern::Zero => ptr::write_bytes(ptr, 0, len),
+            WipePattern::Ones => ptr::write_bytes(ptr, 0xff, len),
+            WipePattern::Byte(b) => ptr::write_bytes(ptr, b, len),
+            WipePattern::Fill(f) => f(slice::from_raw_parts_mut(ptr, len)),
+            WipePattern::Passes(passes) => {
+                for pass in passes {
+                    pass.overwrite(ptr, len);
+                }
+            }
+        }
+        hide_mem_impl::<[u8]>(ptr::slice_from_raw_parts_mut(ptr, len));
+    }
+
+    /// Returns the `n`th single pass of this pattern, or the number of
+    /// single passes if it has no more than `n`.
+    pub(crate) fn nth_pass(&self, mut n: usize) -> Result<&Self, usize> {
+        match *self {
+            WipePattern::Passes(passes) => {
+                let mut count = 0;
+                for pass in passes {
+                    match pass.nth_pass(n) {
+                        Ok(pass) => return Ok(pass),
+                        Err(c) => {
+                            n -= c;
+                            count += c;
+                        }
+                    }
+                }
+                Err(count)
+            }
+            _ if n == 0 => Ok(self),
+            _ => Err(1),
+        }
+    }
+}
+
+impl<'a> fmt::Debug for WipePattern<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WipePattern::Zero => f.write_str("Zero"),
+            WipePattern::Ones => f.write_str("Ones"),
+            WipePattern::Byte(b) => f.debug_tuple("Byte").field(&b).finish(),
+            WipePattern::Fill(_) => f.debug_tuple("Fill").finish(),
+            WipePattern::Passes(passes) => f.debug_tuple("Passes").field(&passes).finish(),
+        }
+    }
+}
+
+impl<'a> Default for WipePattern<'a> {
+    #[inline]
+    fn default() -> Self {
+        WipePattern::Zero
+    }
+}
+
+// Below this size, the setup cost of the vector loops is not worth it.
+const THRESHOLD: usize = 256;
+
+/// Overwrites `len` bytes at `ptr` with zeros.
+///
+/// The caller is responsible for hiding the memory from the optimizer.
+pub(crate) unsafe fn zero_bytes(ptr: *mut u8, len: usize, stores: Stores) {
+    if len < THRESHOLD {
+        ptr::write_bytes(ptr, 0, len);
+    } else {
+        arch::zero_bytes(ptr, len, stores);
+    }
+}
+
+/// Splits the range at `ptr` into an unaligned head, an aligned body
+/// of multiples of `align` bytes, and an unaligned tail, clearing the
+/// head and the tail with `ptr::write_bytes`.
+///
+/// Returns the body as a pointer and a length.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+#[inline]
+unsafe fn clear_unaligned(ptr: *mut u8, len: usize, align: usize) -> (*mut u8, usize) {
+    let head = ptr.align_offset(align).min(len);
+    ptr::write_bytes(ptr, 0, head);
+    let body = (len - head) & !(align - 1);
+    let tail = len - head - body;
+    ptr::write_bytes(ptr.add(head + body), 0, tail);
+    (ptr.add(head), body)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod arch {
+    use core::arch::asm;
+    #[cfg(target_arch = "x86")]
+    use core::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use core::arch::x86_64::*;
+    use core::ptr;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::{clear_unaligned, Stores};
+
+    #[inline]
+    pub unsafe fn zero_bytes(ptr: *mut u8, len: usize, stores: Stores) {
+        if has_avx() {
+            zero_bytes_avx(ptr, len, stores)
+        } else if has_sse2() {
+            zero_bytes_sse2(ptr, len, stores)
+        } else {
+            ptr::write_bytes(ptr, 0, len)
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    fn has_avx() -> bool {
+        is_x86_feature_detected!("avx")
+    }
+
+    #[cfg(not(feature = "std"))]
+    #[inline]
+    fn has_avx() -> bool {
+        cfg!(target_feature = "avx")
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    fn has_sse2() -> bool {
+        is_x86_feature_detected!("sse2")
+    }
+
+    #[cfg(not(feature = "std"))]
+    #[inline]
+    fn has_sse2() -> bool {
+        cfg!(target_feature = "sse2")
+    }
+
+    #[target_feature(enable = "avx")]
+    unsafe fn zero_bytes_avx(ptr: *mut u8, len: usize, stores: Stores) {
+        let (body, len) = clear_unaligned(ptr, len, 32);
+        let zero = _mm256_setzero_si256();
+        let mut p = body as *mut __m256i;
+        let end = body.add(len) as *mut __m256i;
+        match stores {
+            Stores::Regular => {
+                while p < end {
+                    _mm256_store_si256(p, zero);
+                    p = p.add(1);
+                }
+            }
+            Stores::NonTemporal => {
+                while p < end {
+                    _mm256_stream_si256(p, zero);
+                    p = p.add(1);
+                }
+                _mm_sfence();
+            }
+        }
+    }
+
+    #[target_feature(enable = "sse2")]
+    unsafe fn zero_bytes_sse2(ptr: *mut u8, len: usize, stores: Stores) {
+        let (body, len) = clear_unaligned(ptr, len, 16);
+        let zero = _mm_setzero_si128();
+        let mut p = body as *mut __m128i;
+        let end = body.add(len) as *mut __m128i;
+        match stores {
+            Stores::Regular => {
+                while p < end {
+                    _mm_store_si128(p, zero);
+                    p = p.add(1);
+                }
+            }
+            Stores::NonTemporal => {
+                while p < end {
+                    _mm_stream_si128(p, zero);
+                    p = p.add(1);
+                }
+                _mm_sfence();
+            }
+        }
+    }
+
+    // All x86 processors with clflush use 64-byte cache lines.
+    const CACHE_LINE: usize = 64;
+
+    pub unsafe fn flush(ptr: *const u8, len: usize) {
+        let start = ptr as usize & !(CACHE_LINE - 1);
+        let end = ptr as usize + len;
+        if has_clflushopt() {
+            for line in (start..end).step_by(CACHE_LINE) {
+                asm!("clflushopt [{0}]", in(reg) line, options(nostack, preserves_flags));
+            }
+            _mm_sfence();
+        } else if has_sse2() {
+            _mm_mfence();
+            for line in (start..end).step_by(CACHE_LINE) {
+                _mm_clflush(line as *const u8);
+            }
+            _mm_mfence();
+        }
+    }
+
+    fn has_clflushopt() -> bool {
+        // 0 means not yet checked, 1 means not available, 2 means available.
+        static CLFLUSHOPT: AtomicUsize = AtomicUsize::new(0);
+
+        let mut value = CLFLUSHOPT.load(Ordering::Relaxed);
+        if value == 0 {
+            // Leaf 7 returns undefined data if it's above the highest
+            // supported leaf.
+            #[allow(unused_unsafe)]
+            let available =
+                unsafe { __get_cpuid_max(0).0 >= 7 && __cpuid_count(7, 0).ebx & (1 << 23) != 0 };
+            value = if available { 2 } else { 1 };
+            CLFLUSHOPT.store(value, Ordering::Relaxed);
+        }
+        value == 2
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arch {
+    use core::arch::aarch64::*;
+    use core::arch::asm;
+
+    use super::{clear_unaligned, Stores};
+
+    // NEON is always available on aarch64.
+    #[inline]
+    pub unsafe fn zero_bytes(ptr: *mut u8, len: usize, stores: Stores) {
+        let (body, len) = clear_unaligned(ptr, len, 16);
+        let mut p = body;
+        let end = body.add(len);
+        match stores {
+            Stores::Regular => {
+                let zero = vdupq_n_u8(0);
+                while p < end {
+                    vst1q_u8(p, zero);
+                    p = p.add(16);
+                }
+            }
+            Stores::NonTemporal => {
+                while p < end {
+                    asm!("stnp xzr, xzr, [{0}]", in(reg) p, options(nostack, preserves_flags));
+                    p = p.add(16);
+                }
+                asm!("dmb ishst", options(nostack, preserves_flags));
+            }
+        }
+    }
+
+    pub unsafe fn flush(ptr: *const u8, len: usize) {
+        // The smallest data cache line size, in bytes.
+        let ctr: u64;
+        asm!("mrs {0}, ctr_el0", out(reg) ctr, options(nomem, nostack, preserves_flags));
+        let line_size = 4 << ((ctr >> 16) & 0xf);
+
+        let start = ptr as usize & !(line_size - 1);
+        let end = ptr as usize + len;
+        asm!("dsb ish", options(nostack, preserves_flags));
+        for line in (start..end).step_by(line_size) {
+            asm!("dc civac, {0}", in(reg) line, options(nostack, preserves_flags));
+        }
+        asm!("dsb ish", options(nostack, preserves_flags));
+    }
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+mod arch {
+    use core::ptr;
+
+    use super::Stores;
+
+    #[inline]
+    pub unsafe fn zero_bytes(ptr: *mut u8, len: usize, _stores: Stores) {
+        ptr::write_bytes(ptr, 0, len)
+    }
+
+    #[inline]
+    pub unsafe fn flush(_ptr: *const u8, _len: usize) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clear_slice, Stores};
+
+    fn check(stores: Stores) {
+        // Check every combination of unaligned head and tail.
+        let mut buf = vec![0x41u8; 4096 + 64];
+        for start in 0..32 {
+            for end in 4096..4096 + 32 {
+                buf.iter_mut().for_each(|b| *b = 0x41);
+                clear_slice(&mut buf[start..end], stores);
+                assert!(buf[..start].iter().all(|&b| b == 0x41));
+                assert!(buf[start..end].iter().all(|&b| b == 0));
+                assert!(buf[end..].iter().all(|&b| b == 0x41));
+            }
+        }
+    }
+
+    #[test]
+    fn regular() {
+        check(Stores::Regular);
+    }
+
+    #[test]
+    fn non_temporal() {
+        check(Stores::NonTemporal);
+    }
+
+    #[test]
+    fn flush() {
+        let mut buf = vec![0x41u8; 4096 + 64];
+        clear_slice(&mut buf[1..4097], Stores::Regular);
+        super::flush(&buf[1..4097]);
+        assert!(buf[1..4097].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn pattern_passes() {
+        use super::WipePattern;
+        use std::cell::RefCell;
+
+        let seen = RefCell::new(Vec::new());
+        let record = |buf: &mut [u8]| {
+            seen.borrow_mut().push(buf.to_vec());
+            buf.iter_mut().for_each(|b| *b = 0x55);
+        };
+        let passes = [
+            WipePattern::Ones,
+            WipePattern::Fill(&record),
+            WipePattern::Byte(0xaa),
+            WipePattern::Fill(&record),
+        ];
+        let mut buf = [0x41u8; 16];
+        unsafe {
+            WipePattern::Passes(&passes).overwrite(buf.as_mut_ptr(), buf.len());
+        }
+        assert_eq!(*seen.borrow(), [vec![0xff; 16], vec![0xaa; 16]]);
+        assert_eq!(buf, [0x55; 16]);
+    }
+
+    #[test]
+    fn nth_pass() {
+        use super::WipePattern;
+
+        let inner = [WipePattern::Byte(1), WipePattern::Byte(2)];
+        let passes = [
+            WipePattern::Ones,
+            WipePattern::Passes(&inner),
+            WipePattern::Passes(&[]),
+            WipePattern::Byte(3),
+        ];
+        let pattern = WipePattern::Passes(&passes);
+        let bytes: Vec<_> = (0..)
+            .map_while(|n| pattern.nth_pass(n).ok())
+            .map(|pass| match *pass {
+                WipePattern::Ones => 0xff,
+                WipePattern::Byte(b) => b,
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(bytes, [0xff, 1, 2, 3]);
+        assert_eq!(pattern.nth_pass(4).err(), Some(4));
+        assert!(WipePattern::Zero.nth_pass(0).is_ok());
+    }
+
+    #[test]
+    fn stack_passes() {
+        use super::WipePattern;
+        use crate::clear_stack_with;
+        use std::cell::Cell;
+
+        let calls = Cell::new(0);
+        let fill = |buf: &mut [u8]| {
+            calls.set(calls.get() + 1);
+            buf.iter_mut().for_each(|b| *b = 0x55);
+        };
+        let passes = [
+            WipePattern::Fill(&fill),
+            WipePattern::Ones,
+            WipePattern::Fill(&fill),
+        ];
+        clear_stack_with(4, &WipePattern::Passes(&passes));
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn small() {
+        let mut buf = [0x41414141u32; 4];
+        clear_slice(&mut buf, Stores::NonTemporal);
+        assert_eq!(buf, [0; 4]);
+    }
+}
@@ -0,0 +1,265 @@
+//! Checks that clearing survives optimization.
+//!
+//! The clearing done by this crate relies on hiding the cleared memory
+//! from the optimizer (see the crate documentation), and the hiding has
+//! several implementations, depending on the enabled features. Whether
+//! it works can only be checked on the optimized code, by looking at the
+//! memory after it has been cleared and released.
+//!
+//! Each check fills some memory with a sentinel, lets it be cleared and
+//! released, and then counts how many sentinel bytes survived. The
+//! stack checks read memory below the stack pointer right after a
+//! function returns, and the heap check looks at each block as it's
+//! freed, through a `SentinelAllocator`. Reading memory below the stack
+//! pointer is outside of what the language allows; it's done only
+//! through volatile reads, and only without calling other functions in
+//! between, but the results are meaningful only as a test.
+//!
+//! To check the optimized code, run this test with the `verify` profile
+//! (which enables link-time optimization) under each implementation of
+//! the hiding:
+//!
+//! ```text
+//! cargo test --profile verify --features std --test verify
+//! cargo test --profile verify --features std,cc --test verify
+//! cargo test --profile verify --features std,no_asm --test verify
+//! cargo +nightly test --profile verify --features std,nightly --test verify
+//! ```
+//!
+//! The fallback selected with the `no_asm` feature fails the stack check
+//! of `ClearOnDrop`: with link-time optimization, the clearing of a
+//! variable which goes out of scope is removed. When the fallback is
+//! selected, that check instead asserts the clearing is missing under
+//! the `verify` profile, so that the test notices if this changes.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fmt;
+use std::hint::black_box;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use clear_on_drop::{clear_stack, ClearOnDrop};
+
+/// The byte value used to fill memory before it's cleared.
+const SENTINEL: u8 = 0xa5;
+
+const LEN: usize = 256;
+
+// The part of a stack area nearest to the caller is not checked, since
+// unoptimized code can overwrite it when calling the checking functions.
+const SLACK: usize = 1024;
+
+#[global_allocator]
+static ALLOCATOR: SentinelAllocator<System> = SentinelAllocator::new(System);
+
+/// The result of a check.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Report {
+    /// How many bytes were checked.
+    checked: usize,
+    /// How many of them still had the sentinel value.
+    survived: usize,
+}
+
+impl Report {
+    /// Returns true if some bytes were checked, and none of them still
+    /// had the sentinel value.
+    fn is_clean(&self) -> bool {
+        self.checked > 0 && self.survived == 0
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} sentinel bytes survived",
+            self.survived, self.checked
+        )
+    }
+}
+
+/// Counts the sentinel bytes in a region, without calling any function
+/// which could overwrite it.
+#[inline(always)]
+unsafe fn scan(addr: usize, len: usize) -> Report {
+    let mut survived = 0;
+    for i in 0..len {
+        if ptr::read_volatile((addr + i) as *const u8) == SENTINEL {
+            survived += 1;
+        }
+    }
+    Report {
+        checked: len,
+        survived,
+    }
+}
+
+/// Fills a region with the sentinel, in a way which can't be optimized
+/// out, so that only the clearing is being tested.
+#[inline]
+fn fill(buf: &mut [u8]) {
+    for b in buf {
+        unsafe { ptr::write_volatile(b, SENTINEL) };
+    }
+}
+
+/// Checks a `ClearOnDrop` of a stack variable, which goes out of scope
+/// right after being cleared.
+///
+/// This reads memory below the stack pointer, which the language does
+/// not allow; it must only be used as a test.
+unsafe fn check_clear_on_drop_stack() -> Report {
+    let mut addr = 0;
+    black_box(fill_clear_on_drop_stack as fn(&mut usize))(&mut addr);
+    scan(addr, LEN)
+}
+
+#[inline(never)]
+fn fill_clear_on_drop_stack(addr: &mut usize) {
+    let mut buf = [0; LEN + SLACK];
+    fill(&mut buf);
+    let place = ClearOnDrop::new(&mut buf[..]);
+    *addr = place.as_ptr() as usize;
+}
+
+/// Checks `clear_stack`, after a function which left the sentinel on
+/// the stack.
+unsafe fn check_clear_stack() -> Report {
+    let mut addr = 0;
+    black_box(fill_stack as fn(&mut usize))(&mut addr);
+    clear_stack(1);
+    scan(addr, LEN)
+}
+
+/// Checks the stack after a function which left the sentinel on it,
+/// without clearing it.
+///
+/// This is a control for the other checks: it should find all of the
+/// sentinel bytes, showing they would be detected.
+unsafe fn check_unclear_stack() -> Report {
+    let mut addr = 0;
+    black_box(fill_stack as fn(&mut usize))(&mut addr);
+    scan(addr, LEN)
+}
+
+#[inline(never)]
+fn fill_stack(addr: &mut usize) {
+    let mut buf = [0; LEN + SLACK];
+    fill(&mut buf);
+    *addr = buf.as_ptr() as usize;
+}
+
+/// Checks a `ClearOnDrop<Box<[u8]>>`, which is freed right after being
+/// cleared.
+fn check_clear_on_drop_heap() -> Report {
+    let mut place: ClearOnDrop<Box<[u8]>> = ClearOnDrop::new(Box::new([0; LEN]));
+    fill(&mut place);
+    ALLOCATOR.watch(place.as_ptr());
+    drop(place);
+    ALLOCATOR.take_report()
+}
+
+/// Checks a `Box<[u8]>` which is freed without being cleared.
+///
+/// This is a control for the other checks, like `check_unclear_stack`.
+fn check_unclear_heap() -> Report {
+    let mut place: Box<[u8]> = Box::new([0; LEN]);
+    fill(&mut place);
+    ALLOCATOR.watch(place.as_ptr());
+    drop(place);
+    ALLOCATOR.take_report()
+}
+
+/// A memory allocator which looks for sentinel bytes in a block when
+/// it's freed.
+///
+/// It checks memory as it's freed, before its contents can be changed
+/// by the wrapped allocator. Only the block passed to `watch` is
+/// checked.
+struct SentinelAllocator<A> {
+    inner: A,
+    watched: AtomicUsize,
+    checked: AtomicUsize,
+    survived: AtomicUsize,
+}
+
+impl<A> SentinelAllocator<A> {
+    const fn new(inner: A) -> Self {
+        SentinelAllocator {
+            inner,
+            watched: AtomicUsize::new(0),
+            checked: AtomicUsize::new(0),
+            survived: AtomicUsize::new(0),
+        }
+    }
+
+    /// Checks the block starting at `ptr` when it's freed.
+    fn watch<T>(&self, ptr: *const T) {
+        self.checked.store(0, Ordering::SeqCst);
+        self.survived.store(0, Ordering::SeqCst);
+        self.watched.store(ptr as usize, Ordering::SeqCst);
+    }
+
+    /// Returns the result of checking the watched block, and stops
+    /// watching it.
+    fn take_report(&self) -> Report {
+        self.watched.store(0, Ordering::SeqCst);
+        Report {
+            checked: self.checked.load(Ordering::SeqCst),
+            survived: self.survived.load(Ordering::SeqCst),
+        }
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for SentinelAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.inner.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.inner.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if ptr as usize == self.watched.load(Ordering::SeqCst) {
+            let report = scan(ptr as usize, layout.size());
+            self.checked.store(report.checked, Ordering::SeqCst);
+            self.survived.store(report.survived, Ordering::SeqCst);
+        }
+        self.inner.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.inner.realloc(ptr, layout, new_size)
+    }
+}
+
+#[test]
+fn heap() {
+    let report = check_unclear_heap();
+    assert_eq!(report.survived, LEN, "control: {}", report);
+
+    let report = check_clear_on_drop_heap();
+    assert!(report.is_clean(), "ClearOnDrop<Box<[u8]>>: {}", report);
+}
+
+#[test]
+fn stack() {
+    let report = unsafe { check_unclear_stack() };
+    assert_eq!(report.survived, LEN, "control: {}", report);
+
+    let report = unsafe { check_clear_stack() };
+    assert!(report.is_clean(), "clear_stack: {}", report);
+}
+
+#[test]
+fn clear_on_drop_stack() {
+    let report = unsafe { check_clear_on_drop_stack() };
+    if cfg!(all(clear_on_drop_hide = "fallback", clear_on_drop_lto)) {
+        // The known limitation of the fallback hiding.
+        assert_eq!(report.survived, LEN, "ClearOnDrop<&mut [u8]>: {}", report);
+    } else {
+        assert!(report.is_clean(), "ClearOnDrop<&mut [u8]>: {}", report);
+    }
+}